@@ -1,54 +1,229 @@
-use clap::{Args, Parser, Subcommand};
-
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-pub(crate) struct Config {
-    #[command(subcommand)]
-    pub command: Commands,
-}
-
-#[derive(Subcommand, Debug)]
-pub(crate) enum Commands {
-    Proxy(ProxyCommandArgs),
-    Install(InstallCommandArgs),
-    Cli(CliCommandArgs),
-}
-
-#[derive(Args, Debug)]
-pub(crate) struct ProxyCommandArgs {
-    #[arg(long, default_value = "localhost")]
-    pub server_address: String,
-    #[arg(long, default_value_t = 25565)]
-    pub server_port: u16,
-    #[arg(long, default_value = "0.0.0.0")]
-    pub proxy_address: String,
-    #[arg(long, default_value_t = 24565)]
-    pub proxy_port: u16,
-    #[arg(long, default_value = "127.0.0.1:4444")]
-    pub socket: String
-}
-
-#[derive(Args, Debug)]
-pub(crate) struct InstallCommandArgs {
-    #[arg(long, default_value = "localhost")]
-    pub server_address: String,
-    #[arg(long, default_value_t = 25565)]
-    pub server_port: u16,
-    #[arg(long, default_value = "0.0.0.0")]
-    pub proxy_address: String,
-    #[arg(long, default_value_t = 24565)]
-    pub proxy_port: u16,
-    #[arg(long, default_value = "minecraft-maintenance-proxy.service")]
-    pub service_name: String,
-    #[arg(long, default_value = "127.0.0.1:4444")]
-    pub socket: String
-}
-
-#[derive(Args, Debug)]
-pub(crate) struct CliCommandArgs {
-    #[arg(long, default_value = "127.0.0.1:4444")]
-    pub socket: String,
-
-    #[arg(long, default_value = "false")]
-    pub enabling_proxy: String
-}
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use tracing::level_filters::LevelFilter;
+
+use crate::{
+    chat::Motd, cidr::IpNetwork, hostname::HostnameNormalization, listen::ListenPort,
+    proxy::PlayerSample,
+};
+
+/// Shown in the server list and when kicking players during maintenance
+const DEFAULT_MAINTENANCE_MESSAGE: &str = "Server is currently in maintenance";
+
+/// Kick message of players whose login started the server
+const DEFAULT_WAKE_MESSAGE: &str = "Server is starting, try again in a minute";
+
+/// Version name shown in the status response when no better name is known
+const DEFAULT_VERSION_NAME: &str = "1.7.10";
+
+/// Dedicated user and group for the installed service
+const DEFAULT_SERVICE_ACCOUNT: &str = "minecraft-maintenance-proxy";
+
+/// Upper bound for `--kick-delay`, so a tarpitted connection is never held for long
+pub(crate) const KICK_DELAY_MAX_MS: u64 = 5000;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub(crate) struct Config {
+    #[command(subcommand)]
+    pub command: Commands,
+    /// Most verbose level to log: off, error, warn, info, debug or trace
+    #[arg(long, global = true, default_value = "info")]
+    pub log_level: LevelFilter,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Commands {
+    Proxy(Box<ProxyCommandArgs>),
+    Install(InstallCommandArgs),
+    Cli(CliCommandArgs),
+    /// Print whether the proxy is in maintenance or passthrough mode
+    Status(StatusCommandArgs),
+    /// Print the description, version and player counts of any server
+    Ping(PingCommandArgs),
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct ProxyCommandArgs {
+    /// TOML file with the settings of this command, keyed by flag name
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Server to proxy to, a hostname's `_minecraft._tcp` SRV record takes precedence over --server-port
+    #[arg(long, default_value = "localhost")]
+    pub server_address: String,
+    #[arg(long, default_value_t = 25565)]
+    pub server_port: u16,
+    /// Addresses to listen on, such as `0.0.0.0,::` for both IPv4 and IPv6
+    #[arg(long, value_delimiter = ',', default_value = "0.0.0.0")]
+    pub proxy_address: Vec<String>,
+    /// Ports to listen on, `port=host[:port]` proxying one to another server than --server-address
+    #[arg(long, value_delimiter = ',', default_value = "24565")]
+    pub proxy_port: Vec<ListenPort>,
+    /// Control socket, a TCP `host:port` or, on Linux only, `unix:/path/to.sock`
+    #[arg(long, default_value = "127.0.0.1:4444")]
+    pub socket: String,
+    /// Secret control connections must send before any command
+    #[arg(long)]
+    pub control_token: Option<String>,
+    /// URL to POST a JSON notification to when maintenance is toggled or a login is blocked
+    #[cfg(feature = "webhook")]
+    #[arg(long)]
+    pub webhook_url: Option<reqwest::Url>,
+    /// Description shown in the server list during maintenance, plain text or a JSON chat component
+    #[arg(long, default_value = DEFAULT_MAINTENANCE_MESSAGE)]
+    pub motd: Motd,
+    /// Reason shown to players kicked during maintenance
+    #[arg(long, default_value = DEFAULT_MAINTENANCE_MESSAGE)]
+    pub disconnect_message: String,
+    /// Shell command starting the server, run when a player tries to join during maintenance
+    #[arg(long)]
+    pub wake_command: Option<String>,
+    /// Seconds before another join attempt may run the wake command again
+    #[arg(long, default_value_t = 60)]
+    pub wake_cooldown: u64,
+    /// Reason shown to players kicked while the wake command starts the server
+    #[arg(long, default_value = DEFAULT_WAKE_MESSAGE)]
+    pub wake_message: String,
+    /// PNG or JPEG image shown in the server list instead of the built-in icon, scaled to 64x64
+    #[arg(long)]
+    pub favicon: Option<PathBuf>,
+    /// Show the server's real player counts in the maintenance status when it is reachable
+    #[arg(long)]
+    pub forward_status: bool,
+    /// Maximum player count shown in the server list during maintenance
+    #[arg(long, value_parser = clap::value_parser!(i32).range(0..))]
+    pub max_players: Option<i32>,
+    /// Online player count shown in the server list during maintenance
+    #[arg(long, value_parser = clap::value_parser!(i32).range(0..))]
+    pub online_players: Option<i32>,
+    /// `name:uuid` entry of the list shown when hovering the player count, may be repeated
+    #[arg(long)]
+    pub player_sample: Vec<PlayerSample>,
+    /// Send a PROXY protocol v2 header to the server so it sees the players' addresses
+    #[arg(long)]
+    pub proxy_protocol: bool,
+    /// Players let through to the server during maintenance
+    #[arg(long, value_delimiter = ',')]
+    pub whitelist: Vec<String>,
+    /// IPv4 or IPv6 ranges, in CIDR notation, always let through to the server
+    #[arg(long, value_delimiter = ',')]
+    pub allow_ip: Vec<IpNetwork>,
+    /// File of `<protocol> = <version name>` lines merged over the built-in protocol table
+    #[arg(long)]
+    pub protocol_table: Option<PathBuf>,
+    /// Version name shown in the server list, the per-version name takes precedence when known
+    #[arg(long, default_value = DEFAULT_VERSION_NAME)]
+    pub version_name: String,
+    /// Protocol reported in the status response instead of the client's, so it shows as incompatible
+    #[arg(long, allow_negative_numbers = true)]
+    pub version_protocol: Option<i32>,
+    /// Name the version in the status response after the client's protocol
+    #[arg(long)]
+    pub per_version_name: bool,
+    /// Milliseconds to wait before kicking a player who tries to join during maintenance,
+    /// during which the connection still counts towards --max-conns and --max-conns-per-ip
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u64).range(..=KICK_DELAY_MAX_MS))]
+    pub kick_delay: u64,
+    /// Address to serve Prometheus metrics on, disabled when unset
+    #[arg(long)]
+    pub metrics_addr: Option<String>,
+    /// Refuse to start when the server cannot be reached, instead of only warning
+    #[arg(long)]
+    pub fail_fast: bool,
+    /// Seconds between checks of the server, switching to maintenance while it is down
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub health_interval: Option<u64>,
+    /// Seconds a client gets to send its handshake during maintenance
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub handshake_timeout: u64,
+    /// Seconds without any data before a connection is closed, when unset 30 for
+    /// maintenance connections past their handshake and none for proxied ones
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub idle_timeout: Option<u64>,
+    /// Seconds open connections get to finish on shutdown, they are closed at once when unset
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub shutdown_grace_period: Option<u64>,
+    /// Connections handled at once, further ones are dropped
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_conns: Option<usize>,
+    /// Connections handled at once from a single IP address, further ones are dropped
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_conns_per_ip: Option<usize>,
+    /// How the hostname clients connect with is normalized before it is logged
+    #[arg(long, value_enum, default_value_t)]
+    pub hostname_normalization: HostnameNormalization,
+    /// Set by `install` so the proxy reports to the service control manager
+    #[cfg(target_os = "windows")]
+    #[arg(long, hide = true)]
+    pub windows_service: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct InstallCommandArgs {
+    /// Config file for the installed proxy, replacing the other proxy settings below
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    #[arg(long, default_value = "localhost")]
+    pub server_address: String,
+    #[arg(long, default_value_t = 25565)]
+    pub server_port: u16,
+    /// Addresses the installed proxy listens on, as for the `proxy` command
+    #[arg(long, value_delimiter = ',', default_value = "0.0.0.0")]
+    pub proxy_address: Vec<String>,
+    /// Ports the installed proxy listens on, as for the `proxy` command
+    #[arg(long, value_delimiter = ',', default_value = "24565")]
+    pub proxy_port: Vec<ListenPort>,
+    #[arg(long, default_value = "minecraft-maintenance-proxy.service")]
+    pub service_name: String,
+    #[arg(long, default_value = "127.0.0.1:4444")]
+    pub socket: String,
+    #[arg(long, default_value = DEFAULT_MAINTENANCE_MESSAGE)]
+    pub motd: Motd,
+    #[arg(long, default_value = DEFAULT_MAINTENANCE_MESSAGE)]
+    pub disconnect_message: String,
+    /// Replace an existing launchd plist (macOS only)
+    #[arg(long)]
+    pub overwrite: bool,
+    /// Existing user the systemd service runs as
+    #[arg(long, default_value = DEFAULT_SERVICE_ACCOUNT)]
+    pub run_user: String,
+    /// Existing group the systemd service runs as
+    #[arg(long, default_value = DEFAULT_SERVICE_ACCOUNT)]
+    pub run_group: String,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CliCommandArgs {
+    #[arg(long, default_value = "127.0.0.1:4444")]
+    pub socket: String,
+    #[arg(long)]
+    pub control_token: Option<String>,
+
+    /// Defaults to `false` when no other command is given
+    #[arg(long)]
+    pub enabling_proxy: Option<bool>,
+    /// Change the description shown in the server list during maintenance
+    #[arg(long)]
+    pub motd: Option<Motd>,
+    /// Disconnect every player currently connected through the proxy
+    #[arg(long)]
+    pub kick_all: bool,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct StatusCommandArgs {
+    #[arg(long, default_value = "127.0.0.1:4444")]
+    pub socket: String,
+    #[arg(long)]
+    pub control_token: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct PingCommandArgs {
+    /// Server to ping, as `host` or `host:port`
+    pub address: String,
+    /// Seconds to wait for the server to answer
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: u64,
+}
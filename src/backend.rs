@@ -0,0 +1,219 @@
+use std::{
+    cmp::Reverse,
+    fmt,
+    future::Future,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use hickory_resolver::{
+    proto::rr::{rdata::SRV, RData},
+    TokioResolver,
+};
+use tokio::{io, net::TcpStream};
+use tracing::{debug, warn};
+
+use crate::hostname;
+
+/// Upper bound on how long a resolved address is reused, whatever the record's own TTL
+const RESOLUTION_CACHE_MAX_TTL: Duration = Duration::from_secs(60);
+
+/// How long the server's player counts are reused by maintenance status responses
+const PLAYER_COUNTS_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Maximum and online player counts the server reported
+pub(crate) type PlayerCounts = (i32, i32);
+
+/// The Minecraft server behind the proxy.
+///
+/// Like the vanilla client, a hostname is first looked up as a
+/// `_minecraft._tcp` SRV record, which may point to another host and port,
+/// before falling back to its A/AAAA records on the configured port.
+pub(crate) struct Backend {
+    host: String,
+    port: u16,
+    resolver: Option<TokioResolver>,
+    resolved: Mutex<Option<(String, u16, Instant)>>,
+    /// Held while the server is asked, so concurrent pings wait for that answer
+    player_counts: tokio::sync::Mutex<Option<(Option<PlayerCounts>, Instant)>>,
+}
+
+impl Backend {
+    pub(crate) fn new(host: String, port: u16) -> Self {
+        let resolver = match TokioResolver::builder_tokio().and_then(|builder| builder.build()) {
+            Ok(resolver) => Some(resolver),
+            Err(why) => {
+                warn!(
+                    "Unable to read the system DNS configuration, SRV records are ignored: {why}"
+                );
+                None
+            }
+        };
+
+        Self {
+            host,
+            port,
+            resolver,
+            resolved: Mutex::new(None),
+            player_counts: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Host and port to connect to, following the SRV record if there is one
+    pub(crate) async fn resolve(&self) -> (String, u16) {
+        let resolver = match &self.resolver {
+            Some(resolver) if self.host.parse::<IpAddr>().is_err() => resolver,
+            _ => return (self.host.clone(), self.port),
+        };
+
+        if let Some((host, port, valid_until)) = &*self.resolved.lock().unwrap() {
+            if Instant::now() < *valid_until {
+                return (host.clone(), *port);
+            }
+        }
+
+        let (host, port, valid_until) = match lookup_srv(resolver, &self.host).await {
+            Some((host, port, valid_until)) => {
+                debug!("Server {self} resolved to {host}:{port} through its SRV record");
+
+                (host, port, valid_until)
+            }
+            // Without a record, wait as long as allowed before asking again
+            None => (
+                self.host.clone(),
+                self.port,
+                Instant::now() + RESOLUTION_CACHE_MAX_TTL,
+            ),
+        };
+
+        let valid_until = valid_until.min(Instant::now() + RESOLUTION_CACHE_MAX_TTL);
+
+        *self.resolved.lock().unwrap() = Some((host.clone(), port, valid_until));
+
+        (host, port)
+    }
+
+    /// Player counts from `query`, run by one caller at a time and at most once
+    /// every [`PLAYER_COUNTS_CACHE_TTL`], failures included, however many pings arrive
+    pub(crate) async fn player_counts(
+        &self,
+        query: impl Future<Output = Option<PlayerCounts>>,
+    ) -> Option<PlayerCounts> {
+        let mut cached = self.player_counts.lock().await;
+
+        if let Some((counts, valid_until)) = *cached {
+            if Instant::now() < valid_until {
+                return counts;
+            }
+        }
+
+        let counts = query.await;
+
+        *cached = Some((counts, Instant::now() + PLAYER_COUNTS_CACHE_TTL));
+
+        counts
+    }
+
+    pub(crate) async fn connect(&self) -> io::Result<TcpStream> {
+        let (host, port) = self.resolve().await;
+
+        TcpStream::connect((host.as_str(), port)).await
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hostname::format_socket_address(&self.host, self.port))
+    }
+}
+
+/// Looks up the `_minecraft._tcp` SRV record of `host` with the highest priority, with the time it is valid until
+async fn lookup_srv(resolver: &TokioResolver, host: &str) -> Option<(String, u16, Instant)> {
+    let srv_name = format!("_minecraft._tcp.{}.", host.trim_end_matches('.'));
+
+    let lookup = match resolver.srv_lookup(srv_name).await {
+        Ok(lookup) => lookup,
+        Err(why) => {
+            debug!("No SRV record for {host}: {why}");
+            return None;
+        }
+    };
+
+    let records = lookup
+        .answers()
+        .iter()
+        .filter_map(|record| match &record.data {
+            RData::SRV(srv) => Some(srv),
+            _ => None,
+        });
+
+    let (host, port) = select_srv_target(records)?;
+
+    Some((host, port, lookup.valid_until()))
+}
+
+/// Host and port of the record with the highest priority, the heaviest one among equals
+fn select_srv_target<'a>(records: impl IntoIterator<Item = &'a SRV>) -> Option<(String, u16)> {
+    let record = records
+        .into_iter()
+        .min_by_key(|srv| (srv.priority, Reverse(srv.weight)))?;
+
+    let target = record.target.to_utf8();
+
+    Some((target.trim_end_matches('.').to_string(), record.port))
+}
+
+#[cfg(test)]
+mod tests {
+    use hickory_resolver::proto::rr::Name;
+
+    use super::*;
+
+    fn srv(priority: u16, weight: u16, port: u16, target: &str) -> SRV {
+        SRV::new(priority, weight, port, Name::from_ascii(target).unwrap())
+    }
+
+    #[test]
+    fn selects_highest_priority_then_weight() {
+        let records = [
+            srv(10, 100, 25570, "backup.example.com."),
+            srv(0, 5, 25566, "light.example.com."),
+            srv(0, 20, 25567, "heavy.example.com."),
+            srv(5, 50, 25568, "second.example.com."),
+        ];
+
+        assert_eq!(
+            select_srv_target(&records),
+            Some(("heavy.example.com".to_string(), 25567))
+        );
+    }
+
+    #[tokio::test]
+    async fn queries_player_counts_once_for_concurrent_pings() {
+        let backend = Backend::new("127.0.0.1".to_string(), 25565);
+        let queries = std::sync::atomic::AtomicUsize::new(0);
+
+        let query = || async {
+            queries.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+
+            Some((20, 3))
+        };
+
+        let counts = tokio::join!(
+            backend.player_counts(query()),
+            backend.player_counts(query()),
+            backend.player_counts(query()),
+        );
+
+        assert_eq!(counts, (Some((20, 3)), Some((20, 3)), Some((20, 3))));
+        assert_eq!(backend.player_counts(query()).await, Some((20, 3)));
+        assert_eq!(queries.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn selects_nothing_without_records() {
+        assert_eq!(select_srv_target(&[]), None);
+    }
+}
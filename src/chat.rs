@@ -0,0 +1,389 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::{value::RawValue, Value};
+
+/// Colors a chat component may name, in the order of their legacy formatting codes
+const NAMED_COLORS: [&str; 16] = [
+    "black",
+    "dark_blue",
+    "dark_green",
+    "dark_aqua",
+    "dark_red",
+    "dark_purple",
+    "gold",
+    "gray",
+    "dark_gray",
+    "blue",
+    "green",
+    "aqua",
+    "red",
+    "light_purple",
+    "yellow",
+    "white",
+];
+
+const SECTION_SIGN: char = '\u{a7}';
+
+/// Formatted text, as shown in the server list or when kicked.
+///
+/// See https://wiki.vg/Text_formatting#Text_components
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct ChatComponent {
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bold: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    italic: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    underlined: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    strikethrough: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    obfuscated: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    extra: Vec<ChatComponent>,
+}
+
+/// Formatting a component passes down to its `extra` components
+#[derive(Copy, Clone, Default, PartialEq)]
+struct Style<'a> {
+    color: Option<&'a str>,
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+    strikethrough: bool,
+    obfuscated: bool,
+}
+
+impl ChatComponent {
+    fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Reads a component the way the client does: a string, a list whose
+    /// first element holds the others as `extra`, or an object
+    fn from_value(value: &Value) -> anyhow::Result<Self> {
+        match value {
+            Value::String(text) => Ok(Self::text(text)),
+            Value::Array(components) => {
+                let (first, rest) = components
+                    .split_first()
+                    .context("A list of components must not be empty")?;
+
+                let mut component = Self::from_value(first)?;
+
+                for extra in rest {
+                    component.extra.push(Self::from_value(extra)?);
+                }
+
+                Ok(component)
+            }
+            Value::Object(fields) => {
+                let mut component = Self::default();
+
+                for (key, value) in fields {
+                    let flag = || {
+                        value
+                            .as_bool()
+                            .with_context(|| format!("`{key}` must be true or false"))
+                    };
+
+                    match key.as_str() {
+                        "text" => {
+                            component.text = value
+                                .as_str()
+                                .context("`text` must be a string")?
+                                .to_string()
+                        }
+                        "color" => {
+                            let color = value.as_str().context("`color` must be a string")?;
+
+                            if !is_valid_color(color) {
+                                anyhow::bail!("`{color}` is not a color name nor a #RRGGBB color");
+                            }
+
+                            component.color = Some(color.to_string());
+                        }
+                        "bold" => component.bold = Some(flag()?),
+                        "italic" => component.italic = Some(flag()?),
+                        "underlined" => component.underlined = Some(flag()?),
+                        "strikethrough" => component.strikethrough = Some(flag()?),
+                        "obfuscated" => component.obfuscated = Some(flag()?),
+                        "extra" => {
+                            for extra in value.as_array().context("`extra` must be a list")? {
+                                component.extra.push(Self::from_value(extra)?);
+                            }
+                        }
+                        _ => anyhow::bail!("Unsupported component field `{key}`"),
+                    }
+                }
+
+                Ok(component)
+            }
+            _ => anyhow::bail!("A component must be a string, a list or an object"),
+        }
+    }
+
+    /// Renders the component with section sign formatting codes, for clients predating components
+    pub(crate) fn to_legacy_text(&self) -> String {
+        let mut text = String::new();
+
+        self.write_legacy_text(Style::default(), &mut Style::default(), &mut text);
+
+        text
+    }
+
+    fn write_legacy_text<'a>(
+        &'a self,
+        parent: Style<'a>,
+        written: &mut Style<'a>,
+        out: &mut String,
+    ) {
+        let style = Style {
+            color: self.color.as_deref().or(parent.color),
+            bold: self.bold.unwrap_or(parent.bold),
+            italic: self.italic.unwrap_or(parent.italic),
+            underlined: self.underlined.unwrap_or(parent.underlined),
+            strikethrough: self.strikethrough.unwrap_or(parent.strikethrough),
+            obfuscated: self.obfuscated.unwrap_or(parent.obfuscated),
+        };
+
+        if !self.text.is_empty() && style != *written {
+            // A color code clears the formatting codes before it, a reset clears both
+            match style.color.and_then(legacy_color_code) {
+                Some(code) => out.extend([SECTION_SIGN, code]),
+                None => out.extend([SECTION_SIGN, 'r']),
+            }
+
+            for (enabled, code) in [
+                (style.obfuscated, 'k'),
+                (style.bold, 'l'),
+                (style.strikethrough, 'm'),
+                (style.underlined, 'n'),
+                (style.italic, 'o'),
+            ] {
+                if enabled {
+                    out.extend([SECTION_SIGN, code]);
+                }
+            }
+
+            *written = style;
+        }
+
+        out.push_str(&self.text);
+
+        for extra in &self.extra {
+            extra.write_legacy_text(style, written, out);
+        }
+    }
+}
+
+fn is_valid_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => NAMED_COLORS.contains(&color),
+    }
+}
+
+/// Hex colors have no legacy code, they are shown in the default color
+fn legacy_color_code(color: &str) -> Option<char> {
+    let index = NAMED_COLORS.iter().position(|named| *named == color)?;
+
+    char::from_digit(index as u32, 16)
+}
+
+/// Extracts the text of a component another server sent, without its formatting.
+///
+/// Unlike the components given on our own command line, fields this proxy
+/// does not know about are skipped instead of rejected.
+pub(crate) fn plain_text(value: &Value) -> String {
+    let mut text = String::new();
+
+    write_plain_text(value, &mut text);
+
+    // Descriptions sent as strings may still hold formatting codes
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == SECTION_SIGN {
+            chars.next();
+        } else {
+            plain.push(c);
+        }
+    }
+
+    plain
+}
+
+fn write_plain_text(value: &Value, out: &mut String) {
+    match value {
+        Value::String(text) => out.push_str(text),
+        Value::Array(components) => {
+            for component in components {
+                write_plain_text(component, out);
+            }
+        }
+        Value::Object(fields) => {
+            if let Some(text) = fields.get("text") {
+                write_plain_text(text, out);
+            }
+
+            if let Some(extra) = fields.get("extra") {
+                write_plain_text(extra, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A server list description, either plain text or a JSON chat component.
+///
+/// Values starting with `{` or `[` are read as JSON, anything else is shown
+/// as is, section sign formatting codes included.
+#[derive(Clone, Debug)]
+pub(crate) struct Motd {
+    source: String,
+    component: ChatComponent,
+    /// Serialized once, since every status response embeds it
+    json: Box<RawValue>,
+}
+
+impl Motd {
+    pub(crate) fn component(&self) -> &ChatComponent {
+        &self.component
+    }
+
+    pub(crate) fn json(&self) -> &RawValue {
+        &self.json
+    }
+}
+
+impl FromStr for Motd {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let component = if s.trim_start().starts_with(['{', '[']) {
+            serde_json::from_str(s)
+                .map_err(anyhow::Error::from)
+                .and_then(|value| ChatComponent::from_value(&value))
+                .map_err(|why| anyhow::anyhow!("Invalid JSON chat component: {why}"))?
+        } else {
+            ChatComponent::text(s)
+        };
+
+        Ok(Self {
+            source: s.to_string(),
+            json: serde_json::value::to_raw_value(&component)?,
+            component,
+        })
+    }
+}
+
+/// Shows the MOTD as it was given
+impl fmt::Display for Motd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn legacy_text(motd: &str) -> String {
+        motd.parse::<Motd>().unwrap().component().to_legacy_text()
+    }
+
+    #[test]
+    fn keeps_plain_text_as_is() {
+        let motd = "\u{a7}cBack \u{a7}lsoon".parse::<Motd>().unwrap();
+
+        assert_eq!(motd.json().get(), r#"{"text":"§cBack §lsoon"}"#);
+        assert_eq!(motd.component().to_legacy_text(), "\u{a7}cBack \u{a7}lsoon");
+        assert_eq!(motd.to_string(), "\u{a7}cBack \u{a7}lsoon");
+    }
+
+    #[test]
+    fn parses_json_components() {
+        let motd =
+            r#"[{"text":"Down","color":"red","bold":true},{"text":" for a bit","italic":false}]"#
+                .parse::<Motd>()
+                .unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<Value>(motd.json().get()).unwrap(),
+            json!({
+                "text": "Down",
+                "color": "red",
+                "bold": true,
+                "extra": [{ "text": " for a bit", "italic": false }],
+            })
+        );
+    }
+
+    #[test]
+    fn renders_legacy_text() {
+        assert_eq!(
+            legacy_text(r#"{"text":"Down","color":"red","bold":true}"#),
+            "\u{a7}c\u{a7}lDown"
+        );
+
+        // Styles carry over to extra components, and changing them restates the color
+        assert_eq!(
+            legacy_text(
+                r#"{"text":"A","color":"gold","extra":[{"text":"B"},{"text":"C","underlined":true},{"text":"D","color":"white"}]}"#
+            ),
+            "\u{a7}6AB\u{a7}6\u{a7}nC\u{a7}fD"
+        );
+
+        // Hex colors have no code, so they reset to the default color
+        assert_eq!(
+            legacy_text(r##"{"text":"Hex","color":"#ff8800","italic":true}"##),
+            "\u{a7}r\u{a7}oHex"
+        );
+
+        assert_eq!(
+            legacy_text(r#"["Plain ",{"text":"green","color":"green"}]"#),
+            "Plain \u{a7}agreen"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_components() {
+        for invalid in [
+            r#"{"text":"x","color":"pink"}"#,
+            r##"{"text":"x","color":"#ff88"}"##,
+            r##"{"text":"x","color":"#gg8800"}"##,
+            r#"{"text":"x","bold":"yes"}"#,
+            r#"{"text":"x","clickEvent":{}}"#,
+            r#"{"text":1}"#,
+            "[]",
+            "{not json",
+        ] {
+            assert!(invalid.parse::<Motd>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn extracts_plain_text() {
+        assert_eq!(
+            plain_text(&json!({
+                "text": "\u{a7}cA",
+                "color": "red",
+                "unknown": true,
+                "extra": ["B", { "text": "C", "bold": true }],
+            })),
+            "ABC"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use anyhow::Context;
+
+/// An IPv4 or IPv6 range in CIDR notation, such as `192.168.0.0/16` or `fd00::/8`.
+/// A bare address is a range holding only that address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct IpNetwork {
+    address: IpAddr,
+    prefix_length: u8,
+}
+
+/// The address a peer is matched on: dual-stack sockets report IPv4 peers as
+/// IPv4-mapped IPv6 addresses, which are turned back into plain IPv4 ones
+pub(crate) fn canonical_ip(ip: IpAddr) -> IpAddr {
+    ip.to_canonical()
+}
+
+fn mask(address: IpAddr, prefix_length: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(address) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_length as u32).unwrap_or(0);
+
+            IpAddr::V4((u32::from(address) & mask).into())
+        }
+        IpAddr::V6(address) => {
+            let mask = u128::MAX
+                .checked_shl(128 - prefix_length as u32)
+                .unwrap_or(0);
+
+            IpAddr::V6((u128::from(address) & mask).into())
+        }
+    }
+}
+
+impl IpNetwork {
+    pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+        let ip = canonical_ip(ip);
+
+        ip.is_ipv4() == self.address.is_ipv4() && mask(ip, self.prefix_length) == self.address
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_length) = match s.split_once('/') {
+            Some((address, prefix_length)) => (address, Some(prefix_length)),
+            None => (s, None),
+        };
+
+        let address = address
+            .parse::<IpAddr>()
+            .with_context(|| format!("`{address}` is not an IP address"))?;
+
+        let max_prefix_length = if address.is_ipv4() { 32 } else { 128 };
+
+        let prefix_length = match prefix_length {
+            Some(prefix_length) => prefix_length
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_length| *prefix_length <= max_prefix_length)
+                .with_context(|| {
+                    format!("`{prefix_length}` is not a prefix length between 0 and {max_prefix_length}")
+                })?,
+            None => max_prefix_length,
+        };
+
+        // Peers are compared in their canonical form, so store IPv4-mapped ranges as IPv4
+        let (address, prefix_length) = match canonical_ip(address) {
+            IpAddr::V4(mapped) if address.is_ipv6() && prefix_length >= 96 => {
+                (IpAddr::V4(mapped), prefix_length - 96)
+            }
+            _ => (address, prefix_length),
+        };
+
+        Ok(Self {
+            address: mask(address, prefix_length),
+            prefix_length,
+        })
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(s: &str) -> IpNetwork {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn matches_ipv4_prefixes() {
+        let private = network("192.168.1.77/16");
+
+        assert_eq!(private.to_string(), "192.168.0.0/16");
+        assert!(private.contains(ip("192.168.0.1")));
+        assert!(private.contains(ip("192.168.255.255")));
+        assert!(!private.contains(ip("192.169.0.1")));
+        assert!(!private.contains(ip("::ffff:c0a9:1")));
+
+        let host = network("10.0.0.1/32");
+
+        assert!(host.contains(ip("10.0.0.1")));
+        assert!(!host.contains(ip("10.0.0.2")));
+        assert_eq!(network("10.0.0.1"), host);
+
+        let everything = network("0.0.0.0/0");
+
+        assert!(everything.contains(ip("1.2.3.4")));
+        assert!(everything.contains(ip("255.255.255.255")));
+        assert!(!everything.contains(ip("::1")));
+    }
+
+    #[test]
+    fn matches_ipv6_prefixes() {
+        let unique_local = network("fd12:3456::1/8");
+
+        assert_eq!(unique_local.to_string(), "fd00::/8");
+        assert!(unique_local.contains(ip("fdff::1")));
+        assert!(!unique_local.contains(ip("fe80::1")));
+        assert!(!unique_local.contains(ip("10.0.0.1")));
+
+        let host = network("2001:db8::1/128");
+
+        assert!(host.contains(ip("2001:db8::1")));
+        assert!(!host.contains(ip("2001:db8::2")));
+        assert_eq!(network("2001:db8::1"), host);
+
+        let everything = network("::/0");
+
+        assert!(everything.contains(ip("2001:db8::1")));
+        assert!(!everything.contains(ip("1.2.3.4")));
+    }
+
+    #[test]
+    fn matches_ipv4_mapped_peers() {
+        let private = network("192.168.0.0/16");
+
+        assert!(private.contains(ip("::ffff:192.168.3.4")));
+        assert!(!private.contains(ip("::ffff:10.0.0.1")));
+
+        // Ranges written in their IPv4-mapped form match plain IPv4 peers too
+        let mapped = network("::ffff:10.0.0.0/104");
+
+        assert_eq!(mapped, network("10.0.0.0/8"));
+        assert!(mapped.contains(ip("10.1.2.3")));
+        assert!(mapped.contains(ip("::ffff:10.1.2.3")));
+    }
+
+    #[test]
+    fn rejects_invalid_networks() {
+        for invalid in [
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/-1",
+            "10.0.0.0/",
+            "10.0.0.0/x",
+            "10.0.0/8",
+            "example.com/8",
+        ] {
+            assert!(invalid.parse::<IpNetwork>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -1,20 +1,100 @@
-use tokio::{io::AsyncWriteExt, net::TcpStream};
-
-use crate::args;
-
-pub(crate) async fn send_proxy_flag(args: &args::CliCommandArgs) -> anyhow::Result<()> {
-    let socket = args.socket.clone();
-
-    let socket = TcpStream::connect(socket).await?;
-
-    let mut socket = tokio::io::BufStream::new(socket);
-
-    if args.enabling_proxy == "true" {
-        socket.write(&[1]).await?;
-    } else {
-        socket.write(&[0]).await?;
-    };
-
-    socket.flush().await?;
-    Ok(())
-}
\ No newline at end of file
+use anyhow::Context;
+use tokio::io::{AsyncRead, AsyncWrite, BufStream};
+
+use crate::{
+    args,
+    control::{self, ControlCommand, ControlResponse, ControlStream},
+};
+
+pub(crate) async fn send_control_commands(args: &args::CliCommandArgs) -> anyhow::Result<()> {
+    let mut commands = Vec::new();
+
+    if let Some(motd) = &args.motd {
+        commands.push(ControlCommand::SetMotd {
+            motd: motd.to_string(),
+        });
+    }
+
+    if args.kick_all {
+        commands.push(ControlCommand::KickAll);
+    }
+
+    // Without any other command, keep the historical behaviour of disabling the proxy
+    match args.enabling_proxy {
+        Some(enabled) => commands.push(ControlCommand::SetProxy { enabled }),
+        None if commands.is_empty() => commands.push(ControlCommand::SetProxy { enabled: false }),
+        None => {}
+    }
+
+    let mut socket = connect(&args.socket, args.control_token.as_deref()).await?;
+
+    for command in commands {
+        let response = send_command(&mut socket, &command).await?;
+
+        println!("{}", response.message);
+    }
+
+    Ok(())
+}
+
+/// Prints the proxy state as `key: value` lines, meant to be easy to parse from scripts
+pub(crate) async fn print_proxy_state(args: &args::StatusCommandArgs) -> anyhow::Result<()> {
+    let mut socket = connect(&args.socket, args.control_token.as_deref()).await?;
+
+    let state = send_command(&mut socket, &ControlCommand::GetStatus)
+        .await?
+        .state
+        .context("Proxy did not report its state")?;
+
+    let mode = if state.proxying {
+        "passthrough"
+    } else {
+        "maintenance"
+    };
+
+    println!("mode: {mode}");
+    println!("motd: {}", state.motd);
+
+    Ok(())
+}
+
+async fn connect(
+    address: &str,
+    control_token: Option<&str>,
+) -> anyhow::Result<BufStream<Box<dyn ControlStream>>> {
+    let socket = control::connect(address)
+        .await
+        .with_context(|| format!("Could not connect to the control socket {address}"))?;
+
+    let mut socket = BufStream::new(socket);
+
+    if let Some(token) = control_token {
+        let command = ControlCommand::Authenticate {
+            token: token.to_owned(),
+        };
+
+        send_command(&mut socket, &command).await?;
+    }
+
+    Ok(socket)
+}
+
+async fn send_command<S>(
+    socket: &mut S,
+    command: &ControlCommand,
+) -> anyhow::Result<ControlResponse>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    control::write_frame(socket, command).await?;
+
+    let response: ControlResponse = control::read_frame(socket)
+        .await?
+        .context("Control socket closed without a response")?;
+
+    if !response.ok {
+        anyhow::bail!(response.message);
+    }
+
+    Ok(response)
+}
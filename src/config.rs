@@ -0,0 +1,161 @@
+use std::{ffi::OsString, path::Path};
+
+use anyhow::Context;
+use clap::{parser::ValueSource, ArgAction, CommandFactory, FromArgMatches, Parser};
+
+use crate::args;
+
+/// Turns the settings of a config file into `--flag=value` arguments for the `proxy`
+/// subcommand, skipping the flags already given on the command line.
+fn config_file_arguments(
+    path: &Path,
+    proxy_command: &clap::Command,
+    proxy_matches: &clap::ArgMatches,
+) -> anyhow::Result<Vec<OsString>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read config file {}", path.display()))?;
+
+    let settings: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+    let mut arguments = Vec::new();
+
+    for (key, value) in settings {
+        let arg = proxy_command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key.as_str()) && !arg.is_hide_set())
+            .filter(|arg| arg.get_id() != "config")
+            .with_context(|| format!("Unknown setting `{key}` in {}", path.display()))?;
+
+        // Command line flags take precedence over the config file
+        if proxy_matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+
+        for value in values {
+            let value = match (value, arg.get_action()) {
+                (toml::Value::Boolean(true), ArgAction::SetTrue) => {
+                    arguments.push(format!("--{key}").into());
+                    continue;
+                }
+                (toml::Value::Boolean(false), ArgAction::SetTrue) => continue,
+                (toml::Value::String(value), _) => value,
+                (value @ (toml::Value::Integer(_) | toml::Value::Float(_)), _) => value.to_string(),
+                (value, _) => anyhow::bail!(
+                    "Setting `{key}` in {} cannot be {}",
+                    path.display(),
+                    value.type_str()
+                ),
+            };
+
+            arguments.push(format!("--{key}={value}").into());
+        }
+    }
+
+    Ok(arguments)
+}
+
+/// Parses the command line, completing `proxy` arguments with the `--config` file when given.
+///
+/// Values come from, in order of precedence: command line flags, the config file,
+/// then the built-in defaults. Config file values go through the same parsing and
+/// validation as command line flags.
+pub(crate) fn load() -> anyhow::Result<args::Config> {
+    let mut arguments: Vec<OsString> = std::env::args_os().collect();
+
+    let mut command = args::Config::command();
+    // Copies global flags such as --log-level into the subcommands, so the file can set them too
+    command.build();
+
+    let matches = command.clone().get_matches_from(&arguments);
+    let config = args::Config::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    let args::Commands::Proxy(proxy_args) = &config.command else {
+        return Ok(config);
+    };
+
+    let Some(path) = &proxy_args.config else {
+        return Ok(config);
+    };
+
+    let proxy_command = command
+        .find_subcommand("proxy")
+        .context("Missing proxy subcommand")?;
+    let proxy_matches = matches
+        .subcommand_matches("proxy")
+        .context("Missing proxy subcommand")?;
+
+    arguments.extend(config_file_arguments(path, proxy_command, proxy_matches)?);
+
+    args::Config::try_parse_from(arguments).map_err(|err| {
+        let message = err.to_string();
+        let message = message.lines().next().unwrap_or_default();
+
+        anyhow::anyhow!(
+            "Invalid setting in config file {}: {}",
+            path.display(),
+            message.trim_start_matches("error: ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Arguments read from `content` for `command_line`, in a file unique to the calling test
+    fn file_arguments(name: &str, command_line: &[&str], content: &str) -> Vec<OsString> {
+        let path =
+            std::env::temp_dir().join(format!("mmp-config-{}-{name}.toml", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+
+        let mut command = args::Config::command();
+        command.build();
+
+        let matches = command.clone().try_get_matches_from(command_line).unwrap();
+        let arguments = config_file_arguments(
+            &path,
+            command.find_subcommand("proxy").unwrap(),
+            matches.subcommand_matches("proxy").unwrap(),
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        arguments.unwrap()
+    }
+
+    #[test]
+    fn reads_global_flags() {
+        assert_eq!(
+            file_arguments(
+                "global",
+                &["minecraft-maintenance-proxy", "proxy"],
+                "log-level = \"debug\"\nserver-port = 25566\n"
+            ),
+            ["--log-level=debug", "--server-port=25566"]
+        );
+    }
+
+    #[test]
+    fn prefers_command_line_flags() {
+        assert_eq!(
+            file_arguments(
+                "precedence",
+                &[
+                    "minecraft-maintenance-proxy",
+                    "--log-level",
+                    "warn",
+                    "proxy",
+                    "--server-port",
+                    "25567",
+                ],
+                "log-level = \"debug\"\nserver-port = 25566\nforward-status = true\n"
+            ),
+            ["--forward-status"]
+        );
+    }
+}
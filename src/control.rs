@@ -0,0 +1,264 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Frames larger than this are rejected rather than buffered
+const FRAME_MAX_SIZE: u32 = 64 * 1024;
+
+/// Prefix selecting a Unix domain socket instead of a TCP address for `--socket`
+const UNIX_SOCKET_PREFIX: &str = "unix:";
+
+/// Owner and group may toggle the proxy, everyone else is denied by the filesystem
+#[cfg(target_os = "linux")]
+const UNIX_SOCKET_MODE: u32 = 0o660;
+
+pub(crate) trait ControlStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ControlStream for T {}
+
+pub(crate) enum ControlListener {
+    Tcp(TcpListener),
+    #[cfg(target_os = "linux")]
+    Unix {
+        listener: tokio::net::UnixListener,
+        path: std::path::PathBuf,
+    },
+}
+
+impl ControlListener {
+    /// Binds `address`, either a TCP `host:port` or a `unix:/path/to.sock`
+    pub(crate) async fn bind(address: &str) -> anyhow::Result<Self> {
+        let Some(path) = address.strip_prefix(UNIX_SOCKET_PREFIX) else {
+            return Ok(Self::Tcp(TcpListener::bind(address).await?));
+        };
+
+        #[cfg(target_os = "linux")]
+        {
+            use std::{
+                fs,
+                os::unix::fs::{FileTypeExt, PermissionsExt},
+            };
+
+            use anyhow::Context;
+            use nix::sys::stat::{umask, Mode};
+
+            // A previous run killed before cleaning up leaves its socket file behind,
+            // only remove it when it is a socket nobody is listening on anymore
+            match fs::symlink_metadata(path) {
+                Ok(metadata) => {
+                    if !metadata.file_type().is_socket() {
+                        anyhow::bail!("Control socket path {path} exists and is not a socket");
+                    }
+
+                    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                        anyhow::bail!("Control socket {path} is in use by another process");
+                    }
+
+                    fs::remove_file(path)
+                        .with_context(|| format!("Could not remove stale socket {path}"))?;
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err).with_context(|| format!("Could not inspect socket {path}"))
+                }
+            }
+
+            // Create the socket without any permission for others, rather than
+            // leaving it reachable by everyone until its mode is changed
+            let previous_umask = umask(Mode::from_bits_truncate(0o777 & !UNIX_SOCKET_MODE));
+            let listener = tokio::net::UnixListener::bind(path);
+            umask(previous_umask);
+
+            let listener =
+                listener.with_context(|| format!("Could not bind control socket {path}"))?;
+
+            fs::set_permissions(path, fs::Permissions::from_mode(UNIX_SOCKET_MODE))?;
+
+            Ok(Self::Unix {
+                listener,
+                path: path.into(),
+            })
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        anyhow::bail!("Unix control sockets such as {path} are only supported on Linux")
+    }
+
+    pub(crate) async fn accept(&self) -> std::io::Result<Box<dyn ControlStream>> {
+        match self {
+            Self::Tcp(listener) => Ok(Box::new(listener.accept().await?.0)),
+            #[cfg(target_os = "linux")]
+            Self::Unix { listener, .. } => Ok(Box::new(listener.accept().await?.0)),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for ControlListener {
+    fn drop(&mut self) {
+        if let Self::Unix { path, .. } = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Connects to a control socket bound by [`ControlListener::bind`]
+pub(crate) async fn connect(address: &str) -> anyhow::Result<Box<dyn ControlStream>> {
+    let Some(path) = address.strip_prefix(UNIX_SOCKET_PREFIX) else {
+        return Ok(Box::new(TcpStream::connect(address).await?));
+    };
+
+    #[cfg(target_os = "linux")]
+    return Ok(Box::new(tokio::net::UnixStream::connect(path).await?));
+
+    #[cfg(not(target_os = "linux"))]
+    anyhow::bail!("Unix control sockets such as {path} are only supported on Linux")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub(crate) enum ControlCommand {
+    /// Must be the first command when the proxy runs with `--control-token`
+    Authenticate {
+        token: String,
+    },
+    /// Proxy connections to the server when enabled, show the maintenance screen otherwise
+    SetProxy {
+        enabled: bool,
+    },
+    SetMotd {
+        motd: String,
+    },
+    GetStatus,
+    /// Close every client connection
+    KickAll,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ProxyState {
+    pub proxying: bool,
+    pub motd: String,
+}
+
+/// Sent back by the proxy for every command it receives on the control socket
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ControlResponse {
+    pub ok: bool,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<ProxyState>,
+}
+
+impl ControlResponse {
+    pub(crate) fn ok(message: impl Into<String>) -> Self {
+        Self {
+            ok: true,
+            message: message.into(),
+            state: None,
+        }
+    }
+
+    pub(crate) fn error(message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            message: message.into(),
+            state: None,
+        }
+    }
+}
+
+/// Compares tokens in time independent of where they differ, so the secret cannot be guessed byte by byte
+pub(crate) fn tokens_match(token: &str, expected: &str) -> bool {
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Writes `value` as JSON, prefixed with its length as a big-endian `u32`
+pub(crate) async fn write_frame<W, T>(writer: &mut W, value: &T) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let payload = serde_json::to_vec(value)?;
+
+    writer.write_u32(payload.len() as u32).await?;
+    writer.write_all(&payload).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Reads a frame written by [`write_frame`], or `None` if the peer closed the connection first
+pub(crate) async fn read_frame<R, T>(reader: &mut R) -> anyhow::Result<Option<T>>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    match read_raw_frame(reader).await? {
+        Some(payload) => Ok(Some(serde_json::from_slice(&payload)?)),
+        None => Ok(None),
+    }
+}
+
+/// Reads a frame without decoding it, so the caller can answer undecodable payloads
+pub(crate) async fn read_raw_frame<R>(reader: &mut R) -> anyhow::Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    let length = match reader.read_u32().await {
+        Ok(length) => length,
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    if length > FRAME_MAX_SIZE {
+        anyhow::bail!("Control frame of {length} bytes exceeds the {FRAME_MAX_SIZE} bytes limit");
+    }
+
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Some(payload))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn socket_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("mmp-control-{}-{name}.sock", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn replaces_stale_socket_only() {
+        let path = socket_path("stale");
+        let address = format!("{UNIX_SOCKET_PREFIX}{path}");
+
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let listener = ControlListener::bind(&address).await.unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, UNIX_SOCKET_MODE);
+
+        assert!(ControlListener::bind(&address).await.is_err());
+
+        drop(listener);
+        std::fs::write(&path, "not a socket").unwrap();
+        assert!(ControlListener::bind(&address).await.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+use std::{io::Cursor, path::Path};
+
+use base64::prelude::*;
+use image::{imageops, GenericImageView, ImageFormat, RgbaImage};
+use rust_embed::Embed;
+use tracing::{info, warn};
+
+#[derive(Embed)]
+#[folder = "assets"]
+struct Assets;
+
+/// Minecraft only displays server icons of exactly this size
+const FAVICON_SIZE: u32 = 64;
+
+/// Line length of the base64 favicon, as the vanilla server wraps it
+const BASE64_LINE_LENGTH: usize = 76;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Reads the width and height from the IHDR chunk, which must come first in a PNG file
+fn png_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 24 || !data.starts_with(PNG_SIGNATURE) || &data[12..16] != b"IHDR" {
+        return None;
+    }
+
+    let width = u32::from_be_bytes(data[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(data[20..24].try_into().ok()?);
+
+    Some((width, height))
+}
+
+/// Reads a PNG or JPEG image as a 64x64 PNG, scaled to fit and padded with transparency
+fn read_favicon(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+
+    // Icons already fit for the server list are sent untouched
+    if png_dimensions(&data) == Some((FAVICON_SIZE, FAVICON_SIZE)) {
+        return Ok(data);
+    }
+
+    let image = image::load_from_memory(&data)?;
+    let (width, height) = image.dimensions();
+
+    let resized = image
+        .resize(FAVICON_SIZE, FAVICON_SIZE, imageops::FilterType::Lanczos3)
+        .into_rgba8();
+
+    let mut favicon = RgbaImage::new(FAVICON_SIZE, FAVICON_SIZE);
+    imageops::overlay(
+        &mut favicon,
+        &resized,
+        ((FAVICON_SIZE - resized.width()) / 2).into(),
+        ((FAVICON_SIZE - resized.height()) / 2).into(),
+    );
+
+    info!(
+        "Favicon {} resized from {width}x{height} to {FAVICON_SIZE}x{FAVICON_SIZE}",
+        path.display()
+    );
+
+    let mut png = Vec::new();
+    favicon.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+    Ok(png)
+}
+
+/// Loads the icon shown in the server list as the data URI sent in status responses,
+/// falling back to the built-in icon when `path` cannot be used
+pub(crate) fn load_favicon(path: Option<&Path>) -> String {
+    let png = path
+        .and_then(|path| match read_favicon(path) {
+            Ok(png) => Some(png),
+            Err(why) => {
+                warn!(
+                    "Unable to use favicon {}: {why}, using the default one",
+                    path.display()
+                );
+                None
+            }
+        })
+        .unwrap_or_else(|| Assets::get("maintenance.png").unwrap().data.into_owned());
+
+    let encoded = BASE64_STANDARD.encode(png);
+
+    let wrapped = encoded
+        .as_bytes()
+        .chunks(BASE64_LINE_LENGTH)
+        .map(|line| std::str::from_utf8(line).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("data:image/png;base64,{wrapped}")
+}
@@ -0,0 +1,56 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{io, sync::watch::Sender, time::MissedTickBehavior};
+use tracing::info;
+
+use crate::{backend::Backend, proxy::ChannelConfig};
+
+/// How long to wait for the server when checking it is reachable
+const BACKEND_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) async fn probe_backend(backend: &Backend) -> io::Result<()> {
+    match tokio::time::timeout(BACKEND_PROBE_TIMEOUT, backend.connect()).await {
+        Ok(connection) => connection.map(|_| ()),
+        Err(_) => Err(io::ErrorKind::TimedOut.into()),
+    }
+}
+
+/// Switches to maintenance when the server goes down and back to proxying when it comes back.
+///
+/// Only changes in the server's reachability are acted upon, so a flag set
+/// through the control socket is kept until the server goes up or down again.
+pub(crate) async fn monitor_backend(
+    backend: Arc<Backend>,
+    interval: Duration,
+    tx: Sender<ChannelConfig>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut was_reachable = Option::<bool>::None;
+
+    loop {
+        ticker.tick().await;
+
+        let is_reachable = probe_backend(&backend).await.is_ok();
+
+        if was_reachable == Some(is_reachable) {
+            continue;
+        }
+
+        was_reachable = Some(is_reachable);
+
+        let changed = tx.send_if_modified(|config| {
+            let changed = config.is_proxy != is_reachable;
+            config.is_proxy = is_reachable;
+            changed
+        });
+
+        if changed {
+            info!(
+                "Server {backend} is {}, proxy flag set to {is_reachable}",
+                if is_reachable { "up" } else { "down" }
+            );
+        }
+    }
+}
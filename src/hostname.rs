@@ -0,0 +1,162 @@
+use std::net::Ipv6Addr;
+
+use anyhow::Context;
+
+use clap::ValueEnum;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum HostnameNormalization {
+    /// Lowercase the hostname, strip trailing dots, ports and Forge markers
+    #[default]
+    Lowercase,
+    /// Like `lowercase`, but keep the hostname's case
+    KeepCase,
+}
+
+/// Splits the `\0FML\0`, `\0FML2\0` or `\0FML3\0` marker Forge clients append
+/// to the handshake server address, returning the address and the marker.
+pub(crate) fn split_mod_loader(address: &str) -> (&str, Option<&str>) {
+    let mut parts = address.splitn(3, '\0');
+    let hostname = parts.next().unwrap_or_default();
+
+    match (parts.next(), parts.next()) {
+        (Some(marker), Some("")) if marker.starts_with("FML") => (hostname, Some(marker)),
+        _ => (address, None),
+    }
+}
+
+/// Normalizes the server address sent in a handshake, before it is logged
+pub(crate) fn normalize_hostname(address: &str, mode: HostnameNormalization) -> String {
+    // Anything after a NUL byte, such as a Forge marker, is not part of the hostname
+    let hostname = address.split('\0').next().unwrap_or_default();
+
+    let hostname = match hostname.strip_prefix('[') {
+        // Bracketed IPv6 literal, possibly followed by a port
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => match hostname.split_once(':') {
+            // A single colon followed by digits is a port; more colons mean a bare IPv6 literal
+            Some((host, port))
+                if !port.contains(':') && port.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                host
+            }
+            _ => hostname,
+        },
+    };
+
+    let hostname = hostname.trim_end_matches('.');
+
+    match mode {
+        HostnameNormalization::Lowercase => hostname.to_ascii_lowercase(),
+        HostnameNormalization::KeepCase => hostname.to_string(),
+    }
+}
+
+/// Joins a host and a port, bracketing IPv6 literals as in `[::1]:25565`
+pub(crate) fn format_socket_address(host: &str, port: u16) -> String {
+    if host.parse::<Ipv6Addr>().is_ok() {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+/// Splits `host[:port]`, where an IPv6 literal with a port is bracketed as in `[::1]:25565`
+pub(crate) fn split_socket_address(address: &str) -> anyhow::Result<(&str, Option<u16>)> {
+    let (host, port) =
+        match address.strip_prefix('[') {
+            Some(rest) => {
+                let (host, rest) = rest
+                    .split_once(']')
+                    .with_context(|| format!("`{address}` is missing a closing bracket"))?;
+
+                match rest {
+                    "" => (host, None),
+                    _ => (
+                        host,
+                        Some(rest.strip_prefix(':').with_context(|| {
+                            format!("`{address}` must be of the form [host]:port")
+                        })?),
+                    ),
+                }
+            }
+            None => match address.split_once(':') {
+                // More than one colon is a bare IPv6 literal
+                Some((host, port)) if !port.contains(':') => (host, Some(port)),
+                _ => (address, None),
+            },
+        };
+
+    let port = port
+        .map(|port| {
+            port.parse()
+                .with_context(|| format!("`{port}` is not a valid port"))
+        })
+        .transpose()?;
+
+    anyhow::ensure!(!host.is_empty(), "`{address}` has no host");
+
+    Ok((host, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_hostnames() {
+        for (address, lowercase, keep_case) in [
+            ("Play.Example.COM", "play.example.com", "Play.Example.COM"),
+            ("play.example.com.", "play.example.com", "play.example.com"),
+            (
+                "Play.Example.com.:25565",
+                "play.example.com",
+                "Play.Example.com",
+            ),
+            (
+                "Play.Example.com\0FML2\0",
+                "play.example.com",
+                "Play.Example.com",
+            ),
+            (
+                "mc.example.com.\0FML3\0",
+                "mc.example.com",
+                "mc.example.com",
+            ),
+            ("[::1]:25565", "::1", "::1"),
+            ("::1", "::1", "::1"),
+        ] {
+            assert_eq!(
+                normalize_hostname(address, HostnameNormalization::Lowercase),
+                lowercase
+            );
+            assert_eq!(
+                normalize_hostname(address, HostnameNormalization::KeepCase),
+                keep_case
+            );
+        }
+    }
+
+    #[test]
+    fn formats_socket_addresses() {
+        assert_eq!(format_socket_address("192.0.2.1", 25565), "192.0.2.1:25565");
+        assert_eq!(
+            format_socket_address("2001:db8::1", 25565),
+            "[2001:db8::1]:25565"
+        );
+        assert_eq!(format_socket_address("::", 24565), "[::]:24565");
+        assert_eq!(
+            format_socket_address("mc.example.com", 25565),
+            "mc.example.com:25565"
+        );
+    }
+
+    #[test]
+    fn splits_forge_marker() {
+        assert_eq!(
+            split_mod_loader("mc.example.com\0FML2\0"),
+            ("mc.example.com", Some("FML2"))
+        );
+        assert_eq!(split_mod_loader("mc.example.com"), ("mc.example.com", None));
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Instant,
+};
+
+/// Remembers when data last flowed through any of the streams it tracks
+#[derive(Clone)]
+pub(crate) struct IdleTracker {
+    started: Instant,
+    /// Milliseconds between `started` and the last read
+    last_activity: Arc<AtomicU64>,
+}
+
+impl IdleTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_activity: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub(crate) fn track<S>(&self, inner: S) -> Tracked<S> {
+        Tracked {
+            inner,
+            tracker: self.clone(),
+        }
+    }
+
+    fn touch(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+
+        self.last_activity.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// Completes once no data flowed for `timeout`
+    pub(crate) async fn expired(&self, timeout: Duration) {
+        loop {
+            let last_activity =
+                self.started + Duration::from_millis(self.last_activity.load(Ordering::Relaxed));
+
+            if last_activity.elapsed() >= timeout {
+                return;
+            }
+
+            tokio::time::sleep_until(last_activity + timeout).await;
+        }
+    }
+}
+
+/// A stream whose reads count as activity for an [`IdleTracker`]
+pub(crate) struct Tracked<S> {
+    inner: S,
+    tracker: IdleTracker,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Tracked<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if buf.filled().len() > filled {
+            self.tracker.touch();
+        }
+
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Tracked<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
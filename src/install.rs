@@ -1,67 +1,519 @@
-use crate::args;
-use anyhow::Context;
-
-#[cfg(target_os = "linux")]
-pub(crate) fn install_systemd_service(args: &args::InstallCommandArgs) -> anyhow::Result<()> {
-    if !nix::unistd::Uid::effective().is_root() {
-        anyhow::bail!("You must run this executable with root permissions");
-    }
-
-    let (unit_file_name, unit_file) = match args {
-        args::InstallCommandArgs {
-            service_name,
-            server_address,
-            server_port,
-            proxy_address,
-            proxy_port,
-            socket,
-        } => {
-            let executable = std::env::current_exe()?;
-            let executable_location = executable.to_str().context("Invalid executable path")?;
-
-            let service_content = format!(
-                r#"
-[Unit]
-Description=Minecraft Maintenance Proxy
-After=network.target
-
-[Service]
-Type=simple
-User=root
-Group=root
-ExecStart={executable_location} proxy --socket {socket} --server-address {server_address} --server-port {server_port} --proxy-address {proxy_address} --proxy-port {proxy_port}
-
-[Install]
-WantedBy=multi-user.target
-"#
-            );
-
-            (service_name, service_content)
-        }
-    };
-
-    let service_path = format!(
-        "/etc/systemd/system/{unit_file_name}"
-    );
-
-    std::fs::write(&service_path, unit_file)?;
-
-    std::process::Command::new("systemctl")
-        .args(&["daemon-reload"])
-        .status()?;
-
-    std::process::Command::new("systemctl")
-        .args(&["enable", &unit_file_name])
-        .status()?;
-
-    std::process::Command::new("systemctl")
-        .args(&["start", &unit_file_name])
-        .status()?;
-
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-pub(crate) fn install_systemd_service(_args: &args::InstallCommandArgs) -> anyhow::Result<()> {
-    anyhow::bail!("This command is only supported on Linux");
-}
+use crate::args;
+#[cfg(target_os = "linux")]
+use crate::listen::ListenPort;
+use anyhow::Context;
+
+/// Arguments of the `proxy` command the installed service runs
+fn proxy_arguments(args: &args::InstallCommandArgs) -> anyhow::Result<Vec<String>> {
+    if let Some(config) = &args.config {
+        // Services do not start in the current directory
+        let config = std::fs::canonicalize(config)
+            .with_context(|| format!("Unable to find config file {}", config.display()))?;
+        let config = config.to_str().context("Invalid config file path")?;
+
+        return Ok(vec!["proxy".into(), "--config".into(), config.into()]);
+    }
+
+    let mut arguments = [
+        "proxy",
+        "--socket",
+        &args.socket,
+        "--server-address",
+        &args.server_address,
+        "--server-port",
+        &args.server_port.to_string(),
+        "--motd",
+        &args.motd.to_string(),
+        "--disconnect-message",
+        &args.disconnect_message,
+    ]
+    .map(String::from)
+    .to_vec();
+
+    for proxy_address in &args.proxy_address {
+        arguments.extend(["--proxy-address".into(), proxy_address.clone()]);
+    }
+
+    for proxy_port in &args.proxy_port {
+        arguments.extend(["--proxy-port".into(), proxy_port.to_string()]);
+    }
+
+    Ok(arguments)
+}
+
+/// The lowest port the installed proxy listens on, which the config file may set
+#[cfg(target_os = "linux")]
+fn configured_proxy_port(args: &args::InstallCommandArgs) -> anyhow::Result<u16> {
+    let lowest_port = |ports: &[ListenPort]| ports.iter().map(|port| port.port).min();
+
+    let Some(config) = &args.config else {
+        return lowest_port(&args.proxy_port).context("No proxy port given");
+    };
+
+    let settings: toml::Table = toml::from_str(&std::fs::read_to_string(config)?)
+        .with_context(|| format!("Invalid config file {}", config.display()))?;
+
+    let ports = match settings.get("proxy-port") {
+        Some(toml::Value::Array(ports)) => ports.iter().collect(),
+        Some(port) => vec![port],
+        None => return lowest_port(&args.proxy_port).context("No proxy port given"),
+    };
+
+    let invalid_port = || format!("Invalid proxy-port in {}", config.display());
+    let mut lowest_port = u16::MAX;
+
+    for port in ports {
+        let port = match port {
+            toml::Value::Integer(port) => port.to_string(),
+            toml::Value::String(port) => port.clone(),
+            _ => anyhow::bail!(invalid_port()),
+        };
+
+        // Comma separated like on the command line, each possibly mapped to a server
+        for port in port.split(',') {
+            let port = port.parse::<ListenPort>().with_context(invalid_port)?;
+
+            lowest_port = lowest_port.min(port.port);
+        }
+    }
+
+    Ok(lowest_port)
+}
+
+/// Quotes a command line argument for a systemd `ExecStart=` line.
+///
+/// Inside double quotes systemd still expands `%` specifiers and `$` variables
+/// and reads C escapes, and a raw newline would end the setting, so all of them
+/// are escaped to reach the proxy unchanged.
+#[cfg(target_os = "linux")]
+fn quote_systemd_argument(argument: &str) -> String {
+    let mut quoted = String::with_capacity(argument.len() + 2);
+    quoted.push('"');
+
+    for c in argument.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '%' => quoted.push_str("%%"),
+            '$' => quoted.push_str("$$"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u8)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// The systemd unit running `executable` as the proxy configured by `args`
+#[cfg(target_os = "linux")]
+fn systemd_unit_file(executable: &str, args: &args::InstallCommandArgs) -> anyhow::Result<String> {
+    let args::InstallCommandArgs {
+        run_user,
+        run_group,
+        ..
+    } = args;
+
+    let exec_start = std::iter::once(executable.to_string())
+        .chain(proxy_arguments(args)?)
+        .map(|argument| quote_systemd_argument(&argument))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // Only root may bind ports below 1024, unless granted this capability
+    let capabilities = if configured_proxy_port(args)? < 1024 {
+        "AmbientCapabilities=CAP_NET_BIND_SERVICE\nCapabilityBoundingSet=CAP_NET_BIND_SERVICE\n"
+    } else {
+        ""
+    };
+
+    Ok(format!(
+        r#"
+[Unit]
+Description=Minecraft Maintenance Proxy
+After=network.target
+
+[Service]
+Type=simple
+User={run_user}
+Group={run_group}
+{capabilities}ExecStart={exec_start}
+
+[Install]
+WantedBy=multi-user.target
+"#
+    ))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn install_systemd_service(args: &args::InstallCommandArgs) -> anyhow::Result<()> {
+    if !nix::unistd::Uid::effective().is_root() {
+        anyhow::bail!("You must run this executable with root permissions");
+    }
+
+    let args::InstallCommandArgs {
+        service_name: unit_file_name,
+        run_user,
+        run_group,
+        ..
+    } = args;
+
+    if nix::unistd::User::from_name(run_user)?.is_none() {
+        anyhow::bail!(
+            "User {run_user} does not exist, create it or pick another one with --run-user"
+        );
+    }
+
+    if nix::unistd::Group::from_name(run_group)?.is_none() {
+        anyhow::bail!(
+            "Group {run_group} does not exist, create it or pick another one with --run-group"
+        );
+    }
+
+    let executable = std::env::current_exe()?;
+    let executable_location = executable.to_str().context("Invalid executable path")?;
+
+    let unit_file = systemd_unit_file(executable_location, args)?;
+
+    let service_path = format!("/etc/systemd/system/{unit_file_name}");
+
+    std::fs::write(&service_path, unit_file)?;
+
+    std::process::Command::new("systemctl")
+        .args(["daemon-reload"])
+        .status()?;
+
+    std::process::Command::new("systemctl")
+        .args(["enable", unit_file_name])
+        .status()?;
+
+    std::process::Command::new("systemctl")
+        .args(["start", unit_file_name])
+        .status()?;
+
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn install_windows_service(args: &args::InstallCommandArgs) -> anyhow::Result<()> {
+    use std::ffi::OsString;
+
+    use windows_service::{
+        service::{ServiceAccess, ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceType},
+        service_manager::{ServiceManager, ServiceManagerAccess},
+    };
+
+    // The default name is meant for systemd
+    let service_name = args.service_name.trim_end_matches(".service");
+
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .context(
+        "Unable to connect to the service control manager, run this command as an administrator",
+    )?;
+
+    let mut launch_arguments: Vec<OsString> = proxy_arguments(args)?
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+
+    // Right after `proxy`, so the flag belongs to that command
+    launch_arguments.splice(1..1, ["--windows-service".into(), service_name.into()]);
+
+    let service_info = ServiceInfo {
+        name: service_name.into(),
+        display_name: "Minecraft Maintenance Proxy".into(),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments,
+        dependencies: vec![],
+        // LocalSystem
+        account_name: None,
+        account_password: None,
+    };
+
+    let service = manager
+        .create_service(
+            &service_info,
+            ServiceAccess::START | ServiceAccess::QUERY_STATUS,
+        )
+        .with_context(|| format!("Unable to create service {service_name}"))?;
+
+    service
+        .start::<&str>(&[])
+        .with_context(|| format!("Unable to start service {service_name}"))?;
+
+    Ok(())
+}
+
+/// Escapes the characters XML gives a meaning to
+#[cfg(target_os = "macos")]
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn install_launchd_service(args: &args::InstallCommandArgs) -> anyhow::Result<()> {
+    use std::path::PathBuf;
+
+    let args::InstallCommandArgs {
+        service_name,
+        overwrite,
+        ..
+    } = args;
+
+    // The default name is meant for systemd
+    let label = service_name.trim_end_matches(".service");
+
+    // Root installs a daemon started at boot, anyone else an agent started at login
+    let (plist_directory, log_directory) = if nix::unistd::Uid::effective().is_root() {
+        (
+            PathBuf::from("/Library/LaunchDaemons"),
+            PathBuf::from("/Library/Logs"),
+        )
+    } else {
+        let home = PathBuf::from(std::env::var_os("HOME").context("HOME is not set")?);
+
+        (home.join("Library/LaunchAgents"), home.join("Library/Logs"))
+    };
+
+    let plist_path = plist_directory.join(format!("{label}.plist"));
+
+    if plist_path.exists() && !overwrite {
+        anyhow::bail!(
+            "{} already exists, pass --overwrite to replace it",
+            plist_path.display()
+        );
+    }
+
+    let executable = std::env::current_exe()?;
+    let executable_location = executable.to_str().context("Invalid executable path")?;
+
+    let program_arguments = std::iter::once(executable_location.to_string())
+        .chain(proxy_arguments(args)?)
+        .map(|argument| format!("        <string>{}</string>\n", escape_xml(&argument)))
+        .collect::<String>();
+
+    let log_path = log_directory.join(format!("{label}.log"));
+    let log_location = log_path.to_str().context("Invalid log path")?;
+
+    let plist = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{program_arguments}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardErrorPath</key>
+    <string>{log_location}</string>
+</dict>
+</plist>
+"#,
+        label = escape_xml(label),
+        log_location = escape_xml(log_location),
+    );
+
+    std::fs::create_dir_all(&plist_directory)?;
+
+    if plist_path.exists() {
+        // Unloading fails when the previous plist was not loaded, which is fine
+        std::process::Command::new("launchctl")
+            .arg("unload")
+            .arg(&plist_path)
+            .status()?;
+    }
+
+    std::fs::write(&plist_path, plist)?;
+
+    std::process::Command::new("launchctl")
+        .args(["load", "-w"])
+        .arg(&plist_path)
+        .status()?;
+
+    Ok(())
+}
+
+pub(crate) fn install_service(args: &args::InstallCommandArgs) -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    return install_systemd_service(args);
+
+    #[cfg(target_os = "windows")]
+    return install_windows_service(args);
+
+    #[cfg(target_os = "macos")]
+    return install_launchd_service(args);
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+    anyhow::bail!("This command is only supported on Linux, macOS and Windows");
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn parse_install_args(arguments: &[&str]) -> args::InstallCommandArgs {
+        let arguments = ["minecraft-maintenance-proxy", "install"]
+            .iter()
+            .chain(arguments);
+
+        let args::Commands::Install(args) = args::Config::parse_from(arguments).command else {
+            unreachable!()
+        };
+
+        args
+    }
+
+    /// The `proxy` command the service would run with `proxy_arguments`
+    fn installed_proxy_args(args: &args::InstallCommandArgs) -> Box<args::ProxyCommandArgs> {
+        let arguments = std::iter::once("minecraft-maintenance-proxy".to_string())
+            .chain(proxy_arguments(args).unwrap());
+
+        let args::Commands::Proxy(args) = args::Config::parse_from(arguments).command else {
+            unreachable!()
+        };
+
+        args
+    }
+
+    #[test]
+    fn forwards_every_proxy_address() {
+        let args = parse_install_args(&["--proxy-address", "0.0.0.0,::", "--proxy-address", "::1"]);
+
+        assert_eq!(
+            installed_proxy_args(&args).proxy_address,
+            ["0.0.0.0", "::", "::1"]
+        );
+    }
+
+    #[test]
+    fn forwards_every_proxy_port() {
+        let args = parse_install_args(&["--proxy-port", "25565,25566=lobby", "--proxy-port", "80"]);
+
+        let ports = installed_proxy_args(&args)
+            .proxy_port
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(ports, ["25565", "25566=lobby", "80"]);
+        assert_eq!(configured_proxy_port(&args).unwrap(), 80);
+    }
+
+    /// Undoes the doubling of `marker`, which must not introduce anything else,
+    /// as a specifier or a variable would be expanded by systemd
+    fn unescape_doubled(text: &str, marker: char) -> String {
+        let mut unescaped = String::with_capacity(text.len());
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            if c == marker {
+                assert_eq!(chars.next(), Some(marker), "{marker} expanded in {text:?}");
+            }
+
+            unescaped.push(c);
+        }
+
+        unescaped
+    }
+
+    /// Splits an `ExecStart=` value into the arguments systemd runs: specifiers
+    /// are resolved on the whole line, then words are unquoted and unescaped,
+    /// then `$` variables are substituted in each of them
+    fn split_systemd_command_line(command_line: &str) -> Vec<String> {
+        let command_line = unescape_doubled(command_line, '%');
+        let mut chars = command_line.chars().peekable();
+        let mut arguments = Vec::new();
+
+        while let Some(c) = chars.next() {
+            match c {
+                ' ' => continue,
+                '"' => {
+                    let mut argument = String::new();
+
+                    loop {
+                        match chars.next().expect("unterminated quote") {
+                            '"' => break,
+                            '\\' => match chars.next().expect("dangling escape") {
+                                'n' => argument.push('\n'),
+                                'r' => argument.push('\r'),
+                                't' => argument.push('\t'),
+                                'x' => {
+                                    let hex = [chars.next().unwrap(), chars.next().unwrap()];
+                                    let hex = hex.iter().collect::<String>();
+                                    argument.push(u8::from_str_radix(&hex, 16).unwrap() as char);
+                                }
+                                c => argument.push(c),
+                            },
+                            c => argument.push(c),
+                        }
+                    }
+
+                    arguments.push(unescape_doubled(&argument, '$'));
+                }
+                c => panic!("unquoted argument starting with {c:?}"),
+            }
+        }
+
+        arguments
+    }
+
+    #[test]
+    fn round_trips_systemd_exec_start() {
+        let motd = r#"{"text":"Back at 18:00, 100% \"done\" for $USER"}"#;
+        let disconnect_message = "Costs $5 or 50%%\tsoon";
+        let args =
+            parse_install_args(&["--motd", motd, "--disconnect-message", disconnect_message]);
+
+        let unit_file = systemd_unit_file("/opt/mmp/minecraft maintenance proxy", &args).unwrap();
+        let exec_start = unit_file
+            .lines()
+            .find_map(|line| line.strip_prefix("ExecStart="))
+            .unwrap();
+
+        let arguments = split_systemd_command_line(exec_start);
+
+        assert_eq!(arguments[0], "/opt/mmp/minecraft maintenance proxy");
+        assert_eq!(arguments[1..], proxy_arguments(&args).unwrap());
+
+        let args::Commands::Proxy(proxy_args) = args::Config::parse_from(&arguments).command else {
+            unreachable!()
+        };
+        assert_eq!(proxy_args.motd.to_string(), motd);
+        assert_eq!(proxy_args.disconnect_message, disconnect_message);
+    }
+
+    #[test]
+    fn quotes_systemd_arguments() {
+        for (argument, quoted) in [
+            ("localhost", r#""localhost""#),
+            ("Back in a few minutes", r#""Back in a few minutes""#),
+            (r#"{"text":"Down"}"#, r#""{\"text\":\"Down\"}""#),
+            (r"C:\server", r#""C:\\server""#),
+            ("100% down", r#""100%% down""#),
+            ("$HOME", r#""$$HOME""#),
+            ("line one\nline two", r#""line one\nline two""#),
+            ("", r#""""#),
+        ] {
+            assert_eq!(quote_systemd_argument(argument), quoted);
+        }
+    }
+}
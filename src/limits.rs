@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use crate::cidr;
+
+#[derive(Default)]
+struct ActiveConnections {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Caps the number of client connections handled at once, overall and per source address
+pub(crate) struct ConnectionLimiter {
+    max_connections: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    active: Mutex<ActiveConnections>,
+}
+
+/// Holds a connection slot, released when dropped
+pub(crate) struct ConnectionGuard {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl ConnectionLimiter {
+    pub(crate) fn new(
+        max_connections: Option<usize>,
+        max_connections_per_ip: Option<usize>,
+    ) -> Self {
+        Self {
+            max_connections,
+            max_connections_per_ip,
+            active: Mutex::default(),
+        }
+    }
+
+    /// Takes a slot for a connection from `ip`, or `None` if a limit is reached
+    pub(crate) fn try_acquire(self: &Arc<Self>, ip: IpAddr) -> Option<ConnectionGuard> {
+        let ip = cidr::canonical_ip(ip);
+
+        let mut active = self.active.lock().unwrap();
+
+        if self
+            .max_connections
+            .is_some_and(|max_connections| active.total >= max_connections)
+        {
+            return None;
+        }
+
+        // Looked up first so refused addresses do not leave an entry behind
+        let per_ip = active.per_ip.get(&ip).copied().unwrap_or(0);
+
+        if self
+            .max_connections_per_ip
+            .is_some_and(|max_connections_per_ip| per_ip >= max_connections_per_ip)
+        {
+            return None;
+        }
+
+        active.per_ip.insert(ip, per_ip + 1);
+        active.total += 1;
+
+        Some(ConnectionGuard {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut active = self.limiter.active.lock().unwrap();
+
+        active.total -= 1;
+
+        if let Some(per_ip) = active.per_ip.get_mut(&self.ip) {
+            *per_ip -= 1;
+
+            if *per_ip == 0 {
+                active.per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn caps_bursts_from_one_address() {
+        let limiter = Arc::new(ConnectionLimiter::new(Some(10), Some(3)));
+
+        let burst = (0..20)
+            .filter_map(|_| limiter.try_acquire(ip("192.0.2.1")))
+            .collect::<Vec<_>>();
+        assert_eq!(burst.len(), 3);
+
+        // IPv4-mapped peers count as the same address
+        assert!(limiter.try_acquire(ip("::ffff:192.0.2.1")).is_none());
+        assert!(limiter.try_acquire(ip("192.0.2.2")).is_some());
+
+        drop(burst);
+
+        let burst = (0..20)
+            .filter_map(|_| limiter.try_acquire(ip("::ffff:192.0.2.1")))
+            .collect::<Vec<_>>();
+        assert_eq!(burst.len(), 3);
+    }
+
+    #[test]
+    fn caps_bursts_overall() {
+        let limiter = Arc::new(ConnectionLimiter::new(Some(5), None));
+
+        let burst = (0..20)
+            .filter_map(|n| limiter.try_acquire(IpAddr::from([192, 0, 2, n])))
+            .collect::<Vec<_>>();
+        assert_eq!(burst.len(), 5);
+
+        drop(burst);
+
+        assert!(limiter.try_acquire(ip("192.0.2.1")).is_some());
+    }
+
+    #[test]
+    fn forgets_addresses_once_their_connections_end() {
+        let limiter = Arc::new(ConnectionLimiter::new(Some(2), Some(1)));
+
+        let guards = (0..50)
+            .filter_map(|n| limiter.try_acquire(IpAddr::from([192, 0, 2, n])))
+            .collect::<Vec<_>>();
+        assert_eq!(guards.len(), 2);
+        assert_eq!(limiter.active.lock().unwrap().per_ip.len(), 2);
+
+        drop(guards);
+
+        let active = limiter.active.lock().unwrap();
+        assert_eq!(active.total, 0);
+        assert!(active.per_ip.is_empty());
+    }
+}
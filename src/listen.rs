@@ -0,0 +1,183 @@
+use std::{
+    fmt,
+    future::poll_fn,
+    io,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    task::Poll,
+};
+
+use anyhow::Context;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::hostname;
+
+/// Pending connections the kernel queues for each listener
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// A port to listen on, given as `port` or `port=host[:port]` to proxy it to
+/// another server than `--server-address`
+#[derive(Clone, Debug)]
+pub(crate) struct ListenPort {
+    pub(crate) port: u16,
+    pub(crate) server_address: Option<String>,
+    pub(crate) server_port: Option<u16>,
+}
+
+impl FromStr for ListenPort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (port, server) = match s.split_once('=') {
+            Some((port, server)) => (port, Some(server)),
+            None => (s, None),
+        };
+
+        let port = port
+            .parse()
+            .with_context(|| format!("`{port}` is not a valid port"))?;
+
+        let (server_address, server_port) = match server {
+            Some(server) => {
+                let (host, port) = hostname::split_socket_address(server)?;
+
+                (Some(host.to_string()), port)
+            }
+            None => (None, None),
+        };
+
+        Ok(Self {
+            port,
+            server_address,
+            server_port,
+        })
+    }
+}
+
+/// Written back the way it is parsed, to pass it on to another command line
+impl fmt::Display for ListenPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.port)?;
+
+        match (&self.server_address, self.server_port) {
+            (Some(host), Some(port)) => {
+                write!(f, "={}", hostname::format_socket_address(host, port))
+            }
+            (Some(host), None) if host.parse::<Ipv6Addr>().is_ok() => write!(f, "=[{host}]"),
+            (Some(host), None) => write!(f, "={host}"),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+/// Binds `address`, an IP literal or a hostname, on `port`.
+///
+/// With `only_v6`, an IPv6 listener leaves IPv4 to the other listeners instead of
+/// accepting IPv4-mapped connections, so `0.0.0.0` and `::` can share a port.
+pub(crate) async fn bind_listener(
+    address: &str,
+    port: u16,
+    only_v6: bool,
+) -> io::Result<TcpListener> {
+    let ip = match address.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => return TcpListener::bind((address, port)).await,
+    };
+
+    let address = SocketAddr::new(ip, port);
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+
+    if ip.is_ipv6() {
+        socket.set_only_v6(only_v6)?;
+    }
+
+    // Same as tokio, so restarting the proxy does not wait for old connections to time out
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Accepts the next connection on any of `listeners`, along with the index of its listener.
+///
+/// Listeners are polled from `next` on, which then moves past the one that accepted,
+/// so a busy listener cannot starve the others.
+pub(crate) async fn accept_any(
+    listeners: &[TcpListener],
+    next: &mut usize,
+) -> io::Result<(usize, TcpStream, SocketAddr)> {
+    poll_fn(|cx| {
+        for offset in 0..listeners.len() {
+            let index = (*next + offset) % listeners.len();
+
+            if let Poll::Ready(accepted) = listeners[index].poll_accept(cx) {
+                *next = (index + 1) % listeners.len();
+
+                return Poll::Ready(accepted.map(|(socket, peer)| (index, socket, peer)));
+            }
+        }
+
+        Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_listen_ports_back() {
+        for port in [
+            "25565",
+            "25566=lobby",
+            "25567=lobby:25570",
+            "25568=[::1]:25570",
+            "25569=[::1]",
+        ] {
+            assert_eq!(port.parse::<ListenPort>().unwrap().to_string(), port);
+        }
+    }
+
+    #[tokio::test]
+    async fn takes_turns_between_busy_listeners() {
+        let listeners = [
+            bind_listener("127.0.0.1", 0, false).await.unwrap(),
+            bind_listener("127.0.0.1", 0, false).await.unwrap(),
+        ];
+
+        let mut clients = Vec::new();
+
+        for listener in &listeners {
+            for _ in 0..3 {
+                clients.push(
+                    TcpStream::connect(listener.local_addr().unwrap())
+                        .await
+                        .unwrap(),
+                );
+            }
+        }
+
+        // Let the kernel finish every handshake, so both listeners have a backlog
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let mut next = 0;
+        let mut order = Vec::new();
+
+        for _ in 0..6 {
+            let (index, _, _) = accept_any(&listeners, &mut next).await.unwrap();
+            order.push(index);
+        }
+
+        assert_eq!(order, [0, 1, 0, 1, 0, 1]);
+    }
+}
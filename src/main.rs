@@ -1,17 +1,54 @@
 mod args;
+mod backend;
+mod chat;
+mod cidr;
 mod cli;
+mod config;
+mod control;
+mod favicon;
+mod health;
+mod hostname;
+mod idle;
 mod install;
+mod limits;
+mod listen;
+mod metrics;
+mod ping;
 mod proxy;
+mod proxy_protocol;
+#[cfg(target_os = "windows")]
+mod service;
+mod status;
+mod versions;
+mod wake;
+#[cfg(feature = "webhook")]
+mod webhook;
 
-use clap::Parser;
+use std::io::IsTerminal;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = args::Config::parse();
+    let config = config::load()?;
+
+    // Logs go to stderr so `status` output stays easy to parse
+    tracing_subscriber::fmt()
+        .with_max_level(config.log_level)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .init();
 
     match config.command {
-        args::Commands::Cli(args) => cli::send_proxy_flag(&args).await,
-        args::Commands::Proxy(args) => proxy::start_proxy(&args).await,
-        args::Commands::Install(args) => install::install_systemd_service(&args),
+        args::Commands::Cli(args) => cli::send_control_commands(&args).await,
+        args::Commands::Status(args) => cli::print_proxy_state(&args).await,
+        args::Commands::Ping(args) => ping::ping_server(&args).await,
+        args::Commands::Proxy(args) => {
+            #[cfg(target_os = "windows")]
+            if let Some(service_name) = args.windows_service.clone() {
+                return service::run(service_name, *args);
+            }
+
+            proxy::start_proxy(&args, proxy::shutdown_signal()).await
+        }
+        args::Commands::Install(args) => install::install_service(&args),
     }
 }
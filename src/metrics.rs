@@ -0,0 +1,293 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::warn;
+
+/// Requests larger than this are not valid scrapes
+const REQUEST_MAX_SIZE: usize = 8192;
+
+/// Hostnames counted separately, the ones clients send past this share the `other` series
+const HOST_SERIES_MAX: usize = 64;
+
+/// Protocol versions missing from the protocol table share a single bucket,
+/// so clients cannot create an unbounded number of series.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum VersionBucket {
+    Known(i32),
+    Other,
+}
+
+impl std::fmt::Display for VersionBucket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionBucket::Known(protocol) => write!(f, "{protocol}"),
+            VersionBucket::Other => write!(f, "other"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct VersionCounters {
+    connections: u64,
+    login_attempts: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct Metrics {
+    per_version: Mutex<BTreeMap<VersionBucket, VersionCounters>>,
+    /// Keyed by normalized hostname, `None` holding hostnames past [`HOST_SERIES_MAX`]
+    per_host: Mutex<BTreeMap<Option<String>, u64>>,
+    connections: AtomicU64,
+    status_pings: AtomicU64,
+    blocked_logins: AtomicU64,
+    bytes_from_clients: AtomicU64,
+    bytes_from_server: AtomicU64,
+    proxy_enabled: AtomicBool,
+}
+
+impl Metrics {
+    pub(crate) fn record_accepted_connection(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_status_ping(&self) {
+        self.status_pings.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_blocked_login(&self) {
+        self.blocked_logins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_proxied_bytes(&self, from_client: u64, from_server: u64) {
+        self.bytes_from_clients
+            .fetch_add(from_client, Ordering::Relaxed);
+        self.bytes_from_server
+            .fetch_add(from_server, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_proxy_enabled(&self, enabled: bool) {
+        self.proxy_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_connection(&self, bucket: VersionBucket) {
+        self.per_version
+            .lock()
+            .unwrap()
+            .entry(bucket)
+            .or_default()
+            .connections += 1;
+    }
+
+    /// Counts a handshake for `hostname`, which must already be normalized
+    pub(crate) fn record_host_connection(&self, hostname: &str) {
+        let mut per_host = self.per_host.lock().unwrap();
+        let known = per_host.contains_key(&Some(hostname.to_string()));
+
+        // The `other` series is not a hostname, so it does not count towards the limit
+        let hosts = per_host.len() - usize::from(per_host.contains_key(&None));
+
+        let key = (known || hosts < HOST_SERIES_MAX).then(|| hostname.to_string());
+
+        *per_host.entry(key).or_default() += 1;
+    }
+
+    pub(crate) fn record_login_attempt(&self, bucket: VersionBucket) {
+        self.per_version
+            .lock()
+            .unwrap()
+            .entry(bucket)
+            .or_default()
+            .login_attempts += 1;
+    }
+
+    /// Renders the counters in the Prometheus text exposition format
+    pub(crate) fn render(&self) -> String {
+        let per_version = self.per_version.lock().unwrap();
+        let per_host = self.per_host.lock().unwrap();
+        let mut out = String::new();
+
+        let counters = [
+            (
+                "mmp_connections_total",
+                "Client connections accepted.",
+                &self.connections,
+            ),
+            (
+                "mmp_status_pings_total",
+                "Server list pings answered during maintenance.",
+                &self.status_pings,
+            ),
+            (
+                "mmp_logins_blocked_total",
+                "Players kicked because of maintenance.",
+                &self.blocked_logins,
+            ),
+        ];
+
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", counter.load(Ordering::Relaxed));
+        }
+
+        out.push_str("# HELP mmp_proxied_bytes_total Bytes proxied between clients and the server, once connections end.\n");
+        out.push_str("# TYPE mmp_proxied_bytes_total counter\n");
+        let _ = writeln!(
+            out,
+            "mmp_proxied_bytes_total{{direction=\"client_to_server\"}} {}",
+            self.bytes_from_clients.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "mmp_proxied_bytes_total{{direction=\"server_to_client\"}} {}",
+            self.bytes_from_server.load(Ordering::Relaxed)
+        );
+
+        out.push_str("# HELP mmp_proxy_enabled Whether connections are proxied to the server (1) or shown the maintenance screen (0).\n");
+        out.push_str("# TYPE mmp_proxy_enabled gauge\n");
+        let _ = writeln!(
+            out,
+            "mmp_proxy_enabled {}",
+            u8::from(self.proxy_enabled.load(Ordering::Relaxed))
+        );
+
+        out.push_str("# HELP mmp_connections_by_protocol_total Maintenance connections by client protocol version.\n");
+        out.push_str("# TYPE mmp_connections_by_protocol_total counter\n");
+        for (bucket, counters) in per_version.iter() {
+            let _ = writeln!(
+                out,
+                "mmp_connections_by_protocol_total{{protocol=\"{bucket}\"}} {}",
+                counters.connections
+            );
+        }
+
+        out.push_str("# HELP mmp_login_attempts_by_protocol_total Login attempts during maintenance by client protocol version.\n");
+        out.push_str("# TYPE mmp_login_attempts_by_protocol_total counter\n");
+        for (bucket, counters) in per_version.iter() {
+            let _ = writeln!(
+                out,
+                "mmp_login_attempts_by_protocol_total{{protocol=\"{bucket}\"}} {}",
+                counters.login_attempts
+            );
+        }
+
+        out.push_str("# HELP mmp_connections_by_host_total Maintenance connections by hostname in the handshake.\n");
+        out.push_str("# TYPE mmp_connections_by_host_total counter\n");
+        for (host, connections) in per_host.iter() {
+            let _ = writeln!(
+                out,
+                "mmp_connections_by_host_total{{host=\"{}\"}} {connections}",
+                host.as_deref()
+                    .map_or("other".to_string(), escape_label_value)
+            );
+        }
+
+        out
+    }
+}
+
+/// Escapes a label value sent by clients, which may hold any character
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+async fn process_metrics_request(mut socket: TcpStream, metrics: &Metrics) -> anyhow::Result<()> {
+    let mut request = Vec::with_capacity(1024);
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() > REQUEST_MAX_SIZE {
+            anyhow::bail!("Metrics request too large");
+        }
+
+        if socket.read_buf(&mut request).await? == 0 {
+            return Ok(());
+        }
+    }
+
+    let path = request
+        .split(|byte| *byte == b' ')
+        .nth(1)
+        .unwrap_or_default();
+
+    let response = if path == b"/metrics" {
+        let body = metrics.render();
+
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn serve_metrics(
+    listener: TcpListener,
+    metrics: Arc<Metrics>,
+) -> anyhow::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+
+        tokio::spawn(async move {
+            if let Err(why) = process_metrics_request(socket, &metrics).await {
+                warn!("Error serving metrics: {}", why);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_host_series() {
+        let metrics = Metrics::default();
+
+        for n in 0..HOST_SERIES_MAX + 10 {
+            metrics.record_host_connection(&format!("host{n}.example.com"));
+        }
+
+        // Hosts seen before the limit keep their own series
+        metrics.record_host_connection("host0.example.com");
+
+        let rendered = metrics.render();
+
+        assert!(rendered.contains("mmp_connections_by_host_total{host=\"host0.example.com\"} 2\n"));
+        assert!(rendered.contains("mmp_connections_by_host_total{host=\"other\"} 10\n"));
+        assert_eq!(
+            rendered.matches("mmp_connections_by_host_total{").count(),
+            HOST_SERIES_MAX + 1
+        );
+    }
+
+    #[test]
+    fn escapes_host_labels() {
+        let metrics = Metrics::default();
+
+        metrics.record_host_connection("evil\"} 1\nmmp_fake\\");
+
+        assert!(metrics
+            .render()
+            .contains("mmp_connections_by_host_total{host=\"evil\\\"} 1\\nmmp_fake\\\\\"} 1\n"));
+    }
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::{args, backend::Backend, chat, hostname, status};
+
+/// Port the client assumes when an address has none
+const DEFAULT_PORT: u16 = 25565;
+
+/// Handshake protocol version of a client pinging to find out the server's version
+const UNKNOWN_PROTOCOL_VERSION: i32 = -1;
+
+/// Prints a server's status as `key: value` lines, like the server list would show it
+pub(crate) async fn ping_server(args: &args::PingCommandArgs) -> anyhow::Result<()> {
+    let (host, port) = hostname::split_socket_address(&args.address)?;
+    let port = port.unwrap_or(DEFAULT_PORT);
+
+    // Follow the SRV record the same way the proxy does for its own server
+    let backend = Backend::new(host.to_string(), port);
+
+    let json_response = tokio::time::timeout(Duration::from_secs(args.timeout), async {
+        let (host, port) = backend.resolve().await;
+
+        status::query_status(&host, port, UNKNOWN_PROTOCOL_VERSION, false).await
+    })
+    .await
+    .with_context(|| format!("Server {backend} did not answer within {}s", args.timeout))?
+    .with_context(|| format!("Unable to ping {backend}"))?;
+
+    let server_status: status::ServerStatus = serde_json::from_str(&json_response)
+        .with_context(|| format!("Server {backend} sent an invalid status"))?;
+
+    let motd = server_status
+        .description
+        .as_ref()
+        .map(chat::plain_text)
+        .unwrap_or_default();
+
+    println!("motd: {motd}");
+
+    if let Some(version) = server_status.version {
+        println!("version: {} (protocol {})", version.name, version.protocol);
+    }
+
+    if let Some(players) = server_status.players {
+        println!("players: {}/{}", players.online, players.max);
+    }
+
+    Ok(())
+}
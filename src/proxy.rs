@@ -1,465 +1,2750 @@
-use base64::prelude::*;
-use bytes::{Buf, BufMut, BytesMut};
-use nom::{
-    bytes::streaming::take,
-    number::streaming::{be_i64, be_u16, be_u64, be_u8},
-    IResult,
-};
-use rust_embed::Embed;
-use serde::Serialize;
-use tokio::{
-    io::{self, AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
-    sync::watch::Sender,
-};
-
-use crate::args;
-
-#[derive(Embed)]
-#[folder = "assets"]
-struct Assets;
-
-enum ServerboundPacket {
-    Handshake {
-        protocol_version: i32,
-        server_address: String,
-        server_port: u16,
-        next_state: i32,
-    },
-    StatusRequest,
-    PingRequest {
-        payload: i64,
-    },
-    LoginStart {
-        username: String,
-    },
-}
-
-enum ClientboundPacket {
-    PingResponse { payload: i64 },
-    StatusResponse { json_response: String },
-    DisconnectResponse { reason: String },
-}
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum ConnectionState {
-    Handshaking,
-    Status,
-    Login,
-    Play,
-}
-
-#[derive(Debug, Serialize)]
-struct StatusResponse {
-    version: VersionResponse,
-    description: DescriptionResponse,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    players: Option<PlayersResponse>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    favicon: Option<String>,
-}
-
-#[derive(Debug, Serialize)]
-struct VersionResponse {
-    name: String,
-    protocol: i32,
-}
-
-#[derive(Debug, Serialize)]
-struct PlayersResponse {
-    max: i32,
-    online: i32,
-    sample: Vec<PlayerSample>,
-}
-
-#[derive(Debug, Serialize)]
-struct PlayerSample {
-    name: String,
-    id: String,
-}
-
-#[derive(Debug, Serialize)]
-struct DescriptionResponse {
-    text: String,
-}
-
-const SEGMENT_BITS: u8 = 0x7f;
-const CONTINUE_BIT: u8 = 0x80;
-
-fn parse_varint(mut input: &[u8]) -> IResult<&[u8], i32> {
-    let mut value = 0;
-    let mut position = 0;
-
-    loop {
-        let (inner_input, current_byte) = be_u8(input)?;
-
-        value |= ((current_byte & SEGMENT_BITS) as i32) << position;
-
-        if current_byte & CONTINUE_BIT == 0 {
-            return Ok((inner_input, value));
-        }
-
-        position += 7;
-
-        if position > 32 {
-            break Err(nom::Err::Failure(nom::error::Error::new(
-                inner_input,
-                nom::error::ErrorKind::TooLarge,
-            )));
-        }
-
-        input = inner_input;
-    }
-}
-
-fn write_varint(mut value: i32, buf: &mut BytesMut) -> usize {
-    let mut bytes_written = 0;
-
-    loop {
-        let current_value = (value & 0xFF) as u8;
-
-        if current_value & !SEGMENT_BITS == 0 {
-            buf.put_u8(current_value);
-            break bytes_written + 1;
-        }
-
-        buf.put_u8((current_value & SEGMENT_BITS) | CONTINUE_BIT);
-
-        value >>= 7;
-
-        bytes_written += 1;
-    }
-}
-
-fn parse_string(_max_size: usize, input: &[u8]) -> IResult<&[u8], String> {
-    let (input, length) = parse_varint(input)?;
-    let (input, string) = take(length as usize)(input)?;
-
-    Ok((input, String::from_utf8_lossy(string).to_string()))
-}
-
-fn parse_packet(
-    input: &[u8],
-    connection_state: ConnectionState,
-) -> IResult<&[u8], ServerboundPacket> {
-    let (input, packet_id) = parse_varint(input)?;
-
-    match connection_state {
-        ConnectionState::Handshaking => match packet_id {
-            0x00 => {
-                let (input, protocol_version) = parse_varint(input)?;
-                let (input, server_address) = parse_string(255, input)?;
-                let (input, server_port) = be_u16(input)?;
-                let (input, next_state) = parse_varint(input)?;
-
-                Ok((
-                    input,
-                    ServerboundPacket::Handshake {
-                        protocol_version,
-                        server_address,
-                        server_port,
-                        next_state,
-                    },
-                ))
-            }
-            _ => {
-                println!("Packet ID: {packet_id}, Connection State: {connection_state:?}");
-                unimplemented!()
-            }
-        },
-        ConnectionState::Status => match packet_id {
-            0x00 => Ok((input, ServerboundPacket::StatusRequest)),
-            0x01 => {
-                let (input, payload) = be_i64(input)?;
-
-                Ok((input, ServerboundPacket::PingRequest { payload }))
-            }
-            _ => {
-                println!("Packet ID: {packet_id}, Connection State: {connection_state:?}");
-                unimplemented!()
-            }
-        },
-        ConnectionState::Login => match packet_id {
-            0x00 => {
-                let (input, username) = parse_string(16, input)?;
-
-                Ok((
-                    input,
-                    ServerboundPacket::LoginStart {
-                        username,
-                    },
-                ))
-            }
-            _ => {
-                println!("Packet ID: {packet_id}, Connection State: {connection_state:?}");
-                unimplemented!()
-            }
-        },
-        _ => unimplemented!(),
-    }
-}
-
-fn write_packet(packet: ClientboundPacket) -> BytesMut {
-    let packet_buf = match packet {
-        ClientboundPacket::PingResponse { payload } => {
-            let mut buf = BytesMut::with_capacity(9);
-            buf.put_u8(0x01);
-            buf.put_i64(payload);
-
-            buf
-        }
-        ClientboundPacket::StatusResponse { json_response } => {
-            let mut buf = BytesMut::with_capacity(3 + json_response.len());
-
-            write_varint(0x00, &mut buf);
-            write_varint(json_response.len() as i32, &mut buf);
-            buf.put(json_response.as_bytes());
-
-            buf
-        }
-        ClientboundPacket::DisconnectResponse { reason } => {
-            let mut buf = BytesMut::with_capacity(3 + reason.len());
-
-            write_varint(0x00, &mut buf);
-            write_varint(reason.len() as i32, &mut buf);
-            buf.put(reason.as_bytes());
-
-            buf
-        }
-    };
-
-    let mut length_buf = BytesMut::with_capacity(3 + packet_buf.len());
-    let length = packet_buf.len() as i32;
-
-    write_varint(length, &mut length_buf);
-    length_buf.put(packet_buf);
-
-    length_buf
-}
-
-/// See https://wiki.vg/Protocol#Packet_format
-const PACKET_MAX_SIZE: usize = 2097151;
-
-/// Packet length is a varint, which can be up to 3 bytes long
-const PACKET_LENGTH_FIELD_MAX_SIZE: usize = 3;
-
-async fn process_socket(
-    mut socket: TcpStream,
-    minecraft_socket_address: String,
-    should_proxy: bool,
-) -> io::Result<()> {
-    if should_proxy {
-        let mut egress = TcpStream::connect(&minecraft_socket_address).await?;
-
-        match tokio::io::copy_bidirectional(&mut socket, &mut egress).await {
-            Ok((to_egress, to_ingress)) => {
-                println!(
-                    "Connection ended gracefully ({to_egress} bytes from client, {to_ingress} bytes from server)"
-                );
-            }
-            Err(err) => {
-                println!("Error while proxying: {}", err);
-            }
-        }
-        Ok(())
-    } else {
-        let mut buf = BytesMut::with_capacity(2 * PACKET_MAX_SIZE + 1);
-        let mut connection_state = ConnectionState::Handshaking;
-        let mut protocol_version = Option::<i32>::None;
-
-        loop {
-            socket.readable().await?;
-            let n = socket.read_buf(&mut buf).await?;
-
-            if n == 0 {
-                break Ok(());
-            }
-
-            'parse_packets: loop {
-                if buf.is_empty() {
-                    break 'parse_packets;
-                }
-
-                let provisional_packet_length_field_max_size =
-                    PACKET_LENGTH_FIELD_MAX_SIZE.clamp(1, buf.len());
-
-                let (remainder, packet_length) =
-                    parse_varint(&buf[..provisional_packet_length_field_max_size]).unwrap();
-
-                if remainder.len() == 0 {
-                    // Not enough data to parse packet after the length field
-                    break 'parse_packets;
-                }
-
-                let packet_length_field_length =
-                    provisional_packet_length_field_max_size - remainder.len();
-
-                if buf.len() < packet_length as usize + packet_length_field_length {
-                    // Not enough data to parse packet
-                    break 'parse_packets;
-                }
-
-                buf.advance(packet_length_field_length);
-                let packet_buf = buf.split_to(packet_length as usize);
-
-                let (previous_data, packet) = parse_packet(&packet_buf, connection_state).unwrap();
-
-                // Previous data should be empty
-                assert_eq!(previous_data.len(), 0);
-
-                match packet {
-                    ServerboundPacket::Handshake {
-                        protocol_version: packet_protocol_version,
-                        next_state,
-                        ..
-                    } => {
-                        protocol_version = Some(packet_protocol_version);
-
-                        connection_state = match next_state {
-                            1 => ConnectionState::Status,
-                            2 => ConnectionState::Login,
-                            _ => {
-                                eprintln!("Invalid next state: {}", next_state);
-                                break 'parse_packets;
-                            }
-                        };
-                    }
-                    ServerboundPacket::StatusRequest => {
-                        let maintenance_icon = Assets::get("maintenance.png").unwrap();
-
-                        let maintenace_icon_b64 =
-                            BASE64_STANDARD.encode(maintenance_icon.data.as_ref());
-
-                        let wrapped_cols = maintenace_icon_b64
-                            .chars()
-                            .collect::<Vec<_>>()
-                            .chunks(76)
-                            .map(|chars| chars.iter().collect::<String>())
-                            .collect::<Vec<_>>()
-                            .join("\n");
-
-                        let status_response = StatusResponse {
-                            version: VersionResponse {
-                                name: "1.7.10".to_string(),
-                                protocol: protocol_version.unwrap(),
-                            },
-                            description: DescriptionResponse {
-                                text: "Server is currently in maintenance".to_string(),
-                            },
-                            players: None,
-                            favicon: Some(format!("data:image/png;base64,{}", wrapped_cols)),
-                        };
-
-                        let json_response = serde_json::to_string(&status_response).unwrap();
-
-                        let src = write_packet(ClientboundPacket::StatusResponse {
-                            json_response: json_response,
-                        });
-
-                        socket.writable().await?;
-
-                        socket.write_all(&src).await?;
-                    }
-                    ServerboundPacket::PingRequest { payload } => {
-                        let src = write_packet(ClientboundPacket::PingResponse { payload });
-
-                        socket.writable().await?;
-
-                        socket.write_all(&src).await?;
-                    }
-                    ServerboundPacket::LoginStart { .. } => {
-                        let src = write_packet(ClientboundPacket::DisconnectResponse {
-                            reason: "{\"text\": \"Server is currently in maintenance\"}".to_string(),
-                        });
-
-                        socket.writable().await?;
-
-                        socket.write_all(&src).await?;
-                    }
-                }
-            }
-
-            // Reserve space for the next packet
-            buf.reserve(2 * PACKET_MAX_SIZE);
-        }
-    }
-}
-
-struct ChannelConfig {
-    is_proxy: bool,
-}
-
-async fn process_control_socket(
-    mut socket: TcpStream,
-    tx: Sender<ChannelConfig>,
-) -> anyhow::Result<()> {
-    let mut buf = BytesMut::with_capacity(1);
-
-    loop {
-        socket.readable().await?;
-        let n = socket.read_buf(&mut buf).await?;
-
-        if n == 0 {
-            break Ok(());
-        }
-
-        if buf.len() == 1 {
-            let is_proxy = buf.get_u8() == 1;
-
-            println!("Proxy flag set to {is_proxy}");
-
-            tx.send(ChannelConfig { is_proxy })?;
-        }
-    }
-}
-
-pub(crate) async fn start_proxy(args: &args::ProxyCommandArgs) -> anyhow::Result<()> {
-    let (tx, rx) = tokio::sync::watch::channel(ChannelConfig { is_proxy: true });
-
-    let proxy_address = &args.proxy_address;
-    let proxy_port = args.proxy_port;
-
-    let minecraft_address = &args.server_address;
-    let minecraft_port = args.server_port;
-
-    let minecraft_socket_address = format!("{minecraft_address}:{minecraft_port}");
-    let mut should_proxy = true;
-
-    let listener = TcpListener::bind(format!("{proxy_address}:{proxy_port}")).await?;
-    let control_listener = TcpListener::bind(&args.socket).await?;
-
-    loop {
-        let mut rx = rx.clone();
-        let tx = tx.clone();
-        let minecraft_socket_address = minecraft_socket_address.clone();
-
-        tokio::select! {
-            _ = rx.changed() => {
-                should_proxy = rx.borrow().is_proxy;
-            },
-            accepted_socket = listener.accept() => {
-                if let Ok((socket, _)) = accepted_socket {
-                    tokio::spawn(async move {
-                        if let Err(why) = process_socket(socket, minecraft_socket_address, should_proxy).await {
-                            eprintln!("Error: {}", why);
-                        }
-                    });
-                } else {
-                    anyhow::bail!("Error accepting connection");
-                }
-            }
-            accepted_socket = control_listener.accept() => {
-                if let Ok((socket, _)) = accepted_socket {
-                    println!("Accepted control connection");
-                    tokio::spawn(async move {
-                        if let Err(why) = process_control_socket(socket, tx).await {
-                            eprintln!("Error: {}", why);
-                        }
-                    });
-                } else {
-                    anyhow::bail!("Error accepting connection");
-                }
-            }
-        }
-    }
-}
+use anyhow::Context;
+use bytes::{BufMut, BytesMut};
+use nom::{
+    bytes::streaming::take,
+    number::streaming::{be_i64, be_u128, be_u16, be_u8},
+    IResult,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{
+        broadcast,
+        watch::{Receiver, Sender},
+    },
+    time::Instant,
+};
+use tokio_util::task::TaskTracker;
+
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use std::{
+    future::Future,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    args,
+    backend::{Backend, PlayerCounts},
+    chat::Motd,
+    cidr::IpNetwork,
+    control::{self, ControlCommand, ControlListener, ControlResponse, ControlStream, ProxyState},
+    favicon, health,
+    hostname::{self, HostnameNormalization},
+    idle::IdleTracker,
+    limits::ConnectionLimiter,
+    listen,
+    metrics::{self, Metrics, VersionBucket},
+    proxy_protocol, status,
+    versions::ProtocolTable,
+    wake::WakeCommand,
+};
+
+#[cfg(feature = "webhook")]
+use crate::webhook::Webhook;
+
+enum ServerboundPacket {
+    Handshake {
+        protocol_version: i32,
+        server_address: String,
+        /// Marker such as `FML2` appended by modded clients
+        mod_loader: Option<String>,
+        server_port: u16,
+        next_state: i32,
+    },
+    StatusRequest,
+    PingRequest {
+        payload: i64,
+    },
+    LoginStart {
+        username: String,
+        uuid: Option<u128>,
+    },
+    LoginAcknowledged,
+    /// Sent while configuring the client since 1.20.2, the proxy does not act on these yet
+    Configuration {
+        packet_id: i32,
+    },
+}
+
+#[allow(clippy::enum_variant_names)]
+enum ClientboundPacket {
+    PingResponse { payload: i64 },
+    StatusResponse { json_response: String },
+    DisconnectResponse { reason: String },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ConnectionState {
+    Handshaking,
+    Status,
+    Login,
+    Configuration,
+    #[allow(dead_code)]
+    Play,
+}
+
+/// Borrows the parts that do not change between pings, already serialized,
+/// so answering a ping only serializes the version and player counts
+#[derive(Debug, Serialize)]
+struct StatusResponse<'a> {
+    version: VersionResponse<'a>,
+    description: &'a RawValue,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    players: Option<PlayersResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    favicon: Option<&'a RawValue>,
+}
+
+#[derive(Debug, Serialize)]
+struct VersionResponse<'a> {
+    name: &'a str,
+    protocol: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct PlayersResponse {
+    pub(crate) max: i32,
+    pub(crate) online: i32,
+    #[serde(default)]
+    sample: Vec<PlayerSample>,
+}
+
+/// An entry of the player list shown when hovering the player count, given as `name:uuid`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct PlayerSample {
+    name: String,
+    id: String,
+}
+
+impl FromStr for PlayerSample {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Names are free text here, UUIDs never hold a colon
+        let (name, uuid) = s
+            .rsplit_once(':')
+            .with_context(|| format!("`{s}` is not of the form name:uuid"))?;
+
+        let hex = match uuid.len() {
+            32 => uuid.to_string(),
+            36 if [8, 13, 18, 23].iter().all(|&i| uuid.as_bytes()[i] == b'-') => {
+                uuid.replace('-', "")
+            }
+            _ => String::new(),
+        };
+
+        let uuid = Some(hex)
+            .filter(|hex| hex.len() == 32 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u128::from_str_radix(&hex, 16).ok())
+            .with_context(|| format!("`{uuid}` is not a UUID"))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            id: format_uuid(uuid),
+        })
+    }
+}
+
+/// The part of the server's own status response merged into the maintenance one
+#[derive(Debug, Deserialize)]
+struct BackendStatus {
+    players: Option<PlayersResponse>,
+}
+
+const SEGMENT_BITS: u8 = 0x7f;
+const CONTINUE_BIT: u8 = 0x80;
+
+pub(crate) fn parse_varint(mut input: &[u8]) -> IResult<&[u8], i32> {
+    let mut value = 0;
+    let mut position = 0;
+
+    loop {
+        let (inner_input, current_byte) = be_u8(input)?;
+
+        value |= ((current_byte & SEGMENT_BITS) as i32) << position;
+
+        if current_byte & CONTINUE_BIT == 0 {
+            return Ok((inner_input, value));
+        }
+
+        position += 7;
+
+        if position > 32 {
+            break Err(nom::Err::Failure(nom::error::Error::new(
+                inner_input,
+                nom::error::ErrorKind::TooLarge,
+            )));
+        }
+
+        input = inner_input;
+    }
+}
+
+pub(crate) fn write_varint(value: i32, buf: &mut BytesMut) -> usize {
+    // Shift as unsigned so negative values end in the canonical 5 byte form
+    let mut value = value as u32;
+    let mut bytes_written = 0;
+
+    loop {
+        let current_byte = (value & SEGMENT_BITS as u32) as u8;
+
+        value >>= 7;
+        bytes_written += 1;
+
+        if value == 0 {
+            buf.put_u8(current_byte);
+            break bytes_written;
+        }
+
+        buf.put_u8(current_byte | CONTINUE_BIT);
+    }
+}
+
+/// Parses a string of at most `max_size` characters, rejecting longer lengths before buffering them
+pub(crate) fn parse_string(max_size: usize, input: &[u8]) -> IResult<&[u8], String> {
+    let too_long = |input| {
+        nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::TooLarge,
+        ))
+    };
+
+    let (rest, length) = parse_varint(input)?;
+
+    // A character takes up to 3 bytes in the UTF-8 the protocol uses
+    let length = usize::try_from(length).map_err(|_| too_long(input))?;
+
+    if length > max_size * 3 {
+        return Err(too_long(input));
+    }
+
+    let (rest, string) = take(length)(rest)?;
+    let string = String::from_utf8_lossy(string).to_string();
+
+    if string.chars().count() > max_size {
+        return Err(too_long(input));
+    }
+
+    Ok((rest, string))
+}
+
+/// Rejects a packet ID the proxy does not handle in the current connection state
+fn unknown_packet(input: &[u8]) -> IResult<&[u8], ServerboundPacket> {
+    Err(nom::Err::Failure(nom::error::Error::new(
+        input,
+        nom::error::ErrorKind::Switch,
+    )))
+}
+
+fn describe_parse_error(err: &nom::Err<nom::error::Error<&[u8]>>) -> String {
+    match err {
+        nom::Err::Incomplete(_) => "truncated packet".to_string(),
+        nom::Err::Error(err) | nom::Err::Failure(err) => match err.code {
+            nom::error::ErrorKind::Switch => "unknown packet ID".to_string(),
+            nom::error::ErrorKind::TooLarge => "string too long".to_string(),
+            code => format!("malformed packet ({})", code.description()),
+        },
+    }
+}
+
+/// Parses what follows the username in Login Start, which changed several times since 1.19.
+///
+/// See https://wiki.vg/Protocol_History
+fn parse_login_start_uuid(input: &[u8], protocol_version: i32) -> IResult<&[u8], Option<u128>> {
+    let input = if matches!(protocol_version, 759 | 760) {
+        // 1.19 to 1.19.2 may send the player's chat signing key
+        let (input, has_sig_data) = be_u8(input)?;
+
+        if has_sig_data != 0 {
+            let (input, _timestamp) = be_i64(input)?;
+            let (input, public_key_length) = parse_varint(input)?;
+            let (input, _public_key) = take(public_key_length as usize)(input)?;
+            let (input, signature_length) = parse_varint(input)?;
+            let (input, _signature) = take(signature_length as usize)(input)?;
+
+            input
+        } else {
+            input
+        }
+    } else {
+        input
+    };
+
+    match protocol_version {
+        ..=759 => Ok((input, None)),
+        760..=763 => {
+            let (input, has_player_uuid) = be_u8(input)?;
+
+            if has_player_uuid != 0 {
+                let (input, uuid) = be_u128(input)?;
+
+                Ok((input, Some(uuid)))
+            } else {
+                Ok((input, None))
+            }
+        }
+        _ => {
+            let (input, uuid) = be_u128(input)?;
+
+            Ok((input, Some(uuid)))
+        }
+    }
+}
+
+fn format_uuid(uuid: u128) -> String {
+    let hex = format!("{uuid:032x}");
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn parse_packet(
+    input: &[u8],
+    connection_state: ConnectionState,
+    protocol_version: Option<i32>,
+) -> IResult<&[u8], ServerboundPacket> {
+    let (input, packet_id) = parse_varint(input)?;
+
+    match connection_state {
+        ConnectionState::Handshaking => match packet_id {
+            0x00 => {
+                let (input, protocol_version) = parse_varint(input)?;
+                let (input, server_address) = parse_string(255, input)?;
+                let (input, server_port) = be_u16(input)?;
+                let (input, next_state) = parse_varint(input)?;
+
+                let (server_address, mod_loader) = hostname::split_mod_loader(&server_address);
+
+                Ok((
+                    input,
+                    ServerboundPacket::Handshake {
+                        protocol_version,
+                        server_address: server_address.to_string(),
+                        mod_loader: mod_loader.map(str::to_string),
+                        server_port,
+                        next_state,
+                    },
+                ))
+            }
+            _ => unknown_packet(input),
+        },
+        ConnectionState::Status => match packet_id {
+            0x00 => Ok((input, ServerboundPacket::StatusRequest)),
+            0x01 => {
+                let (input, payload) = be_i64(input)?;
+
+                Ok((input, ServerboundPacket::PingRequest { payload }))
+            }
+            _ => unknown_packet(input),
+        },
+        ConnectionState::Login => match packet_id {
+            0x00 => {
+                let (input, username) = parse_string(16, input)?;
+                let (input, uuid) =
+                    parse_login_start_uuid(input, protocol_version.unwrap_or_default())?;
+
+                Ok((input, ServerboundPacket::LoginStart { username, uuid }))
+            }
+            // Login Acknowledged moves 1.20.2+ clients to the configuration state
+            0x03 if protocol_version.is_some_and(|version| version >= 764) => {
+                Ok((input, ServerboundPacket::LoginAcknowledged))
+            }
+            _ => unknown_packet(input),
+        },
+        ConnectionState::Configuration => Ok((
+            &input[input.len()..],
+            ServerboundPacket::Configuration { packet_id },
+        )),
+        _ => unknown_packet(input),
+    }
+}
+
+fn write_packet(packet: ClientboundPacket) -> BytesMut {
+    let packet_buf = match packet {
+        ClientboundPacket::PingResponse { payload } => {
+            let mut buf = BytesMut::with_capacity(9);
+            buf.put_u8(0x01);
+            buf.put_i64(payload);
+
+            buf
+        }
+        ClientboundPacket::StatusResponse { json_response } => {
+            let mut buf = BytesMut::with_capacity(3 + json_response.len());
+
+            write_varint(0x00, &mut buf);
+            write_varint(json_response.len() as i32, &mut buf);
+            buf.put(json_response.as_bytes());
+
+            buf
+        }
+        ClientboundPacket::DisconnectResponse { reason } => {
+            let mut buf = BytesMut::with_capacity(3 + reason.len());
+
+            write_varint(0x00, &mut buf);
+            write_varint(reason.len() as i32, &mut buf);
+            buf.put(reason.as_bytes());
+
+            buf
+        }
+    };
+
+    frame_packet(packet_buf)
+}
+
+/// Prefixes a packet with its length
+pub(crate) fn frame_packet(packet_buf: BytesMut) -> BytesMut {
+    let mut length_buf = BytesMut::with_capacity(3 + packet_buf.len());
+    let length = packet_buf.len() as i32;
+
+    write_varint(length, &mut length_buf);
+    length_buf.put(packet_buf);
+
+    length_buf
+}
+
+/// Why the next frame of a connection cannot be read
+enum FrameError {
+    InvalidLength,
+    Oversized(i32),
+}
+
+/// Splits the next frame off `buf`, along with the size of its length field,
+/// rejecting packets longer than `max_size`.
+///
+/// Returns `None` until the whole frame was read, after making room in `buf` for the rest of it.
+fn next_frame(
+    buf: &mut BytesMut,
+    max_size: usize,
+) -> Result<Option<(usize, BytesMut)>, FrameError> {
+    if buf.is_empty() {
+        return Ok(None);
+    }
+
+    let provisional_packet_length_field_max_size = PACKET_LENGTH_FIELD_MAX_SIZE.clamp(1, buf.len());
+
+    let (packet_length_field_length, packet_length) =
+        match parse_varint(&buf[..provisional_packet_length_field_max_size]) {
+            Ok((remainder, packet_length)) => (
+                provisional_packet_length_field_max_size - remainder.len(),
+                packet_length,
+            ),
+            // The length field itself is split across reads
+            Err(nom::Err::Incomplete(_)) if buf.len() < PACKET_LENGTH_FIELD_MAX_SIZE => {
+                return Ok(None);
+            }
+            Err(_) => return Err(FrameError::InvalidLength),
+        };
+
+    // Checked before waiting for the rest, so a bogus length cannot hold the connection open
+    let packet_length = match usize::try_from(packet_length) {
+        Ok(packet_length) if packet_length <= max_size => packet_length,
+        _ => return Err(FrameError::Oversized(packet_length)),
+    };
+
+    let frame_length = packet_length_field_length + packet_length;
+
+    if buf.len() < frame_length {
+        // Not enough data to parse packet, make room for the rest of it
+        buf.reserve(frame_length - buf.len());
+
+        return Ok(None);
+    }
+
+    Ok(Some((
+        packet_length_field_length,
+        buf.split_to(frame_length),
+    )))
+}
+
+/// Writes the kick packet that pre-1.7 clients expect in answer to a legacy ping or login.
+///
+/// See https://wiki.vg/Server_List_Ping#1.6
+fn write_legacy_kick(reason: &str) -> BytesMut {
+    let reason = reason.encode_utf16().collect::<Vec<_>>();
+    let mut buf = BytesMut::with_capacity(3 + 2 * reason.len());
+
+    buf.put_u8(LEGACY_KICK_PACKET_ID);
+    buf.put_u16(reason.len() as u16);
+    reason.iter().for_each(|unit| buf.put_u16(*unit));
+
+    buf
+}
+
+/// First byte sent by pre-1.7 clients pinging the server, which is not a valid packet length
+const LEGACY_PING_PACKET_ID: u8 = 0xfe;
+
+/// First byte sent by pre-1.7 clients joining the server
+const LEGACY_LOGIN_PACKET_ID: u8 = 0x02;
+
+const LEGACY_KICK_PACKET_ID: u8 = 0xff;
+
+/// Protocol advertised to legacy clients, which never matches theirs so the version name is shown
+const LEGACY_PING_PROTOCOL_VERSION: i32 = 127;
+
+/// See https://wiki.vg/Protocol#Packet_format
+pub(crate) const PACKET_MAX_SIZE: usize = 2097151;
+
+/// Packet length is a varint, which can be up to 3 bytes long
+const PACKET_LENGTH_FIELD_MAX_SIZE: usize = 3;
+
+/// Enough for handshakes and status pings, larger packets grow the buffer as needed
+const INITIAL_BUFFER_SIZE: usize = 512;
+
+/// How long to wait for the server's own status before answering without player counts
+const STATUS_FORWARD_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Kick message of players let through while the server cannot be reached
+const BACKEND_UNAVAILABLE_MESSAGE: &str = "Backend unavailable";
+
+/// Idle timeout of maintenance connections past their handshake when `--idle-timeout` is unset,
+/// matching how long vanilla servers wait on a silent client
+const MAINTENANCE_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Room for the status response fields besides the favicon and the MOTD, players included
+const STATUS_RESPONSE_EXTRA_SIZE: usize = 1024;
+
+/// Size of the buffer each direction of a proxied connection is copied through
+const FORWARD_BUFFER_SIZE: usize = 8192;
+
+/// Settings shared by every connection, built once on startup
+struct ProxyConfig {
+    control_token: Option<String>,
+    protocol_table: ProtocolTable,
+    version_name: String,
+    version_protocol: Option<i32>,
+    per_version_name: bool,
+    kick_delay: Duration,
+    metrics: Arc<Metrics>,
+    hostname_normalization: HostnameNormalization,
+    disconnect_message: String,
+    wake_command: Option<WakeCommand>,
+    wake_message: String,
+    #[cfg(feature = "webhook")]
+    webhook: Option<Webhook>,
+    /// Data URI of the server icon, encoded once on startup
+    favicon: Box<RawValue>,
+    forward_status: bool,
+    max_players: Option<i32>,
+    online_players: Option<i32>,
+    player_sample: Vec<PlayerSample>,
+    proxy_protocol: bool,
+    handshake_timeout: Duration,
+    idle_timeout: Option<Duration>,
+    whitelist: Vec<String>,
+    allowed_networks: Vec<IpNetwork>,
+}
+
+impl ProxyConfig {
+    /// Reads the settings from the command line, loading the files they point to
+    fn from_args(args: &args::ProxyCommandArgs) -> anyhow::Result<Self> {
+        let mut protocol_table = ProtocolTable::builtin();
+
+        if let Some(path) = &args.protocol_table {
+            protocol_table.merge_file(path)?;
+        }
+
+        Ok(Self {
+            control_token: args.control_token.clone(),
+            protocol_table,
+            version_name: args.version_name.clone(),
+            version_protocol: args.version_protocol,
+            per_version_name: args.per_version_name,
+            kick_delay: Duration::from_millis(args.kick_delay),
+            metrics: Arc::new(Metrics::default()),
+            hostname_normalization: args.hostname_normalization,
+            disconnect_message: args.disconnect_message.clone(),
+            wake_command: args
+                .wake_command
+                .clone()
+                .map(|command| WakeCommand::new(command, Duration::from_secs(args.wake_cooldown))),
+            wake_message: args.wake_message.clone(),
+            #[cfg(feature = "webhook")]
+            webhook: args.webhook_url.clone().map(Webhook::new).transpose()?,
+            favicon: serde_json::value::to_raw_value(&favicon::load_favicon(
+                args.favicon.as_deref(),
+            ))?,
+            forward_status: args.forward_status,
+            max_players: args.max_players,
+            online_players: args.online_players,
+            player_sample: args.player_sample.clone(),
+            proxy_protocol: args.proxy_protocol,
+            handshake_timeout: Duration::from_secs(args.handshake_timeout),
+            idle_timeout: args.idle_timeout.map(Duration::from_secs),
+            whitelist: args.whitelist.clone(),
+            allowed_networks: args.allow_ip.clone(),
+        })
+    }
+
+    /// Status response shown during maintenance, serialized into a buffer of
+    /// [`Self::status_json_size`] since the favicon makes up most of it
+    fn status_json(
+        &self,
+        motd: &Motd,
+        protocol_version: i32,
+        players: Option<PlayersResponse>,
+    ) -> serde_json::Result<String> {
+        let status_response = StatusResponse {
+            version: VersionResponse {
+                name: self.version_name(protocol_version),
+                // Echoing the client's protocol keeps the server listed as compatible
+                protocol: self.version_protocol.unwrap_or(protocol_version),
+            },
+            description: motd.json(),
+            players,
+            favicon: Some(&self.favicon),
+        };
+
+        let mut json_response = Vec::with_capacity(self.status_json_size(motd));
+        serde_json::to_writer(&mut json_response, &status_response)?;
+
+        Ok(String::from_utf8(json_response).expect("serde_json only writes UTF-8"))
+    }
+
+    /// Room for the favicon and `motd`, copied as is, and the rest of the status response
+    fn status_json_size(&self, motd: &Motd) -> usize {
+        self.favicon.get().len() + motd.json().get().len() + STATUS_RESPONSE_EXTRA_SIZE
+    }
+
+    fn version_name(&self, protocol_version: i32) -> &str {
+        if self.per_version_name {
+            self.protocol_table
+                .version_name(protocol_version)
+                .unwrap_or(&self.version_name)
+        } else {
+            &self.version_name
+        }
+    }
+
+    /// The server's player counts to show in the maintenance status, shared between
+    /// pings arriving within a few seconds of each other so they don't each query the server
+    async fn backend_players(
+        &self,
+        backend: &Backend,
+        protocol_version: i32,
+    ) -> Option<PlayersResponse> {
+        let counts = backend
+            .player_counts(async {
+                self.query_backend_players(backend, protocol_version)
+                    .await
+                    .unwrap_or_else(|why| {
+                        warn!("Unable to get player counts from the server: {why}");
+                        None
+                    })
+            })
+            .await;
+
+        counts.map(|(max, online)| PlayersResponse {
+            max,
+            online,
+            sample: Vec::new(),
+        })
+    }
+
+    /// Asks the server for its maximum and online player counts
+    async fn query_backend_players(
+        &self,
+        backend: &Backend,
+        protocol_version: i32,
+    ) -> anyhow::Result<Option<PlayerCounts>> {
+        let json_response = tokio::time::timeout(STATUS_FORWARD_TIMEOUT, async {
+            let (host, port) = backend.resolve().await;
+
+            status::query_status(&host, port, protocol_version, self.proxy_protocol).await
+        })
+        .await
+        .context("Timed out")??;
+
+        let backend_status: BackendStatus = serde_json::from_str(&json_response)?;
+
+        Ok(backend_status
+            .players
+            .map(|players| (players.max, players.online)))
+    }
+
+    /// Player counts shown during maintenance, configured ones taking precedence over the server's
+    fn maintenance_players(&self, forwarded: Option<PlayersResponse>) -> Option<PlayersResponse> {
+        if self.max_players.is_none()
+            && self.online_players.is_none()
+            && self.player_sample.is_empty()
+        {
+            return forwarded;
+        }
+
+        let (max, online) = forwarded.map_or((0, 0), |players| (players.max, players.online));
+
+        Some(PlayersResponse {
+            max: self.max_players.unwrap_or(max),
+            online: self.online_players.unwrap_or(online),
+            sample: self.player_sample.clone(),
+        })
+    }
+
+    /// Wakes the server up when configured to, and returns the reason to kick the player with
+    fn blocked_login_reason(&self) -> &str {
+        match &self.wake_command {
+            Some(wake_command) => {
+                wake_command.trigger();
+
+                &self.wake_message
+            }
+            None => &self.disconnect_message,
+        }
+    }
+
+    fn is_whitelisted(&self, username: &str) -> bool {
+        self.whitelist
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(username))
+    }
+
+    fn version_bucket(&self, protocol_version: i32) -> VersionBucket {
+        match self.protocol_table.version_name(protocol_version) {
+            Some(_) => VersionBucket::Known(protocol_version),
+            None => VersionBucket::Other,
+        }
+    }
+}
+
+/// Connects to the server on behalf of the client connected on `socket`
+async fn connect_to_server(
+    socket: &TcpStream,
+    peer: SocketAddr,
+    backend: &Backend,
+    settings: &ProxyConfig,
+) -> io::Result<TcpStream> {
+    let mut egress = backend.connect().await?;
+
+    if settings.proxy_protocol {
+        let header = proxy_protocol::encode_v2_header(peer, socket.local_addr()?);
+
+        egress.write_all(&header).await?;
+    }
+
+    Ok(egress)
+}
+
+/// Proxies a connection the proxy already read from, replaying `consumed` to the
+/// server first so it sees the connection from its very first byte
+async fn hand_over_to_server(
+    socket: TcpStream,
+    peer: SocketAddr,
+    consumed: &[u8],
+    backend: &Backend,
+    settings: &ProxyConfig,
+) -> io::Result<()> {
+    let mut egress = match connect_to_server(&socket, peer, backend, settings).await {
+        Ok(egress) => egress,
+        Err(why) => {
+            warn!("Unable to connect to {backend}: {why}");
+
+            // The login start was read, so the client is waiting for a login packet
+            return send_login_disconnect(socket, BACKEND_UNAVAILABLE_MESSAGE).await;
+        }
+    };
+
+    egress.write_all(consumed).await?;
+
+    proxy_to_server(socket, egress, settings).await
+}
+
+/// Login disconnect packet showing `reason` to the client
+fn login_disconnect_packet(reason: &str) -> BytesMut {
+    // Every client since 1.7 reads the reason as a JSON chat component
+    write_packet(ClientboundPacket::DisconnectResponse {
+        reason: serde_json::json!({ "text": reason }).to_string(),
+    })
+}
+
+async fn send_login_disconnect(mut socket: TcpStream, reason: &str) -> io::Result<()> {
+    socket.write_all(&login_disconnect_packet(reason)).await?;
+    socket.shutdown().await
+}
+
+/// Closes a connection that should have been let through while the server is unreachable.
+///
+/// Nothing was read from the client yet, so its handshake is read first: a client
+/// logging in is told why it cannot join, anything else is simply closed.
+async fn reject_unavailable_backend(
+    mut socket: TcpStream,
+    settings: &ProxyConfig,
+) -> io::Result<()> {
+    let mut buf = BytesMut::with_capacity(INITIAL_BUFFER_SIZE);
+
+    let handshake = tokio::time::timeout(settings.handshake_timeout, async {
+        loop {
+            match next_frame(&mut buf, PACKET_MAX_SIZE) {
+                Ok(Some((packet_length_field_length, frame))) => {
+                    let packet = &frame[packet_length_field_length..];
+
+                    return Ok(parse_packet(packet, ConnectionState::Handshaking, None)
+                        .ok()
+                        .map(|(_, packet)| packet));
+                }
+                Ok(None) => {}
+                Err(_) => return Ok(None),
+            }
+
+            if socket.read_buf(&mut buf).await? == 0 {
+                return Ok(None);
+            }
+        }
+    })
+    .await;
+
+    match handshake {
+        Ok(Ok(Some(ServerboundPacket::Handshake {
+            next_state: 2 | 3, ..
+        }))) => send_login_disconnect(socket, BACKEND_UNAVAILABLE_MESSAGE).await,
+        Ok(result) => result.map(|_| ()),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Why [`forward`] stopped, telling apart the end it was reading from and the one it was writing to
+#[derive(Debug)]
+enum ForwardError {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+impl ForwardError {
+    /// The error along with the side of the connection it happened on
+    fn blame(self, reader: &'static str, writer: &'static str) -> (&'static str, io::Error) {
+        match self {
+            Self::Read(why) => (reader, why),
+            Self::Write(why) => (writer, why),
+        }
+    }
+}
+
+/// Copies `reader` to `writer` until the end of `reader`, counting the bytes in `forwarded`
+async fn forward<R, W>(
+    mut reader: R,
+    mut writer: W,
+    forwarded: &AtomicU64,
+) -> Result<(), ForwardError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0; FORWARD_BUFFER_SIZE];
+
+    loop {
+        let n = reader.read(&mut buf).await.map_err(ForwardError::Read)?;
+
+        if n == 0 {
+            // Pass the end of stream on, the other direction may still have data to send
+            return writer.shutdown().await.map_err(ForwardError::Write);
+        }
+
+        writer
+            .write_all(&buf[..n])
+            .await
+            .map_err(ForwardError::Write)?;
+        forwarded.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+async fn proxy_to_server(
+    socket: TcpStream,
+    egress: TcpStream,
+    settings: &ProxyConfig,
+) -> io::Result<()> {
+    let idle_timeout = settings.idle_timeout;
+
+    let tracker = IdleTracker::new();
+    let (client_read, client_write) = socket.into_split();
+    let (server_read, server_write) = egress.into_split();
+
+    let from_client = AtomicU64::new(0);
+    let from_server = AtomicU64::new(0);
+
+    let transfer = async {
+        let upload = async {
+            forward(tracker.track(client_read), server_write, &from_client)
+                .await
+                .map_err(|why| why.blame("client", "server"))
+        };
+        let download = async {
+            forward(tracker.track(server_read), client_write, &from_server)
+                .await
+                .map_err(|why| why.blame("server", "client"))
+        };
+        tokio::pin!(upload, download);
+
+        // After a clean close, the other side may still finish what it was sending
+        tokio::select! {
+            result = &mut upload => match result {
+                Ok(()) => download.await.map(|()| "client"),
+                Err(why) => Err(why),
+            },
+            result = &mut download => match result {
+                Ok(()) => upload.await.map(|()| "server"),
+                Err(why) => Err(why),
+            },
+        }
+    };
+
+    let idle = async {
+        match idle_timeout {
+            Some(idle_timeout) => tracker.expired(idle_timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let outcome = tokio::select! {
+        outcome = transfer => Some(outcome),
+        _ = idle => None,
+    };
+
+    let (from_client, from_server) = (from_client.into_inner(), from_server.into_inner());
+
+    settings
+        .metrics
+        .record_proxied_bytes(from_client, from_server);
+
+    match outcome {
+        Some(Ok(closed_first)) => info!(
+            bytes_from_client = from_client,
+            bytes_from_server = from_server,
+            closed_first,
+            "Connection ended gracefully"
+        ),
+        Some(Err((failed_side, why))) => warn!(
+            bytes_from_client = from_client,
+            bytes_from_server = from_server,
+            failed_side,
+            "Error while proxying: {why}"
+        ),
+        None => info!(
+            bytes_from_client = from_client,
+            bytes_from_server = from_server,
+            "Closing connection idle for {}s",
+            idle_timeout.unwrap_or_default().as_secs()
+        ),
+    }
+
+    Ok(())
+}
+
+async fn process_socket(
+    mut socket: TcpStream,
+    peer: SocketAddr,
+    should_proxy: bool,
+    config: Receiver<ChannelConfig>,
+    backend: Arc<Backend>,
+    settings: Arc<ProxyConfig>,
+) -> io::Result<()> {
+    settings.metrics.record_accepted_connection();
+
+    let allowed_network = settings
+        .allowed_networks
+        .iter()
+        .find(|network| network.contains(peer.ip()));
+
+    if let (false, Some(network)) = (should_proxy, allowed_network) {
+        info!(%network, "Letting connection through, its address is allowed");
+    }
+
+    if should_proxy || allowed_network.is_some() {
+        let egress = match connect_to_server(&socket, peer, &backend, &settings).await {
+            Ok(egress) => egress,
+            Err(why) => {
+                warn!("Unable to connect to {backend}: {why}");
+
+                return reject_unavailable_backend(socket, &settings).await;
+            }
+        };
+
+        proxy_to_server(socket, egress, &settings).await
+    } else {
+        let mut buf = BytesMut::with_capacity(INITIAL_BUFFER_SIZE);
+        let mut connection_state = ConnectionState::Handshaking;
+        let mut protocol_version = Option::<i32>::None;
+        // Frames read before the login is decided, kept as received in case the
+        // connection has to be handed over to the server
+        let mut consumed = BytesMut::new();
+        let handshake_deadline = Instant::now() + settings.handshake_timeout;
+
+        loop {
+            let read = async {
+                socket.readable().await?;
+                socket.read_buf(&mut buf).await
+            };
+
+            let n = match (connection_state, settings.idle_timeout) {
+                // Counted from the connection start, so trickling bytes does not extend it
+                (ConnectionState::Handshaking, _) => {
+                    match tokio::time::timeout_at(handshake_deadline, read).await {
+                        Ok(n) => n?,
+                        Err(_) => {
+                            info!(
+                                "Closing connection without a handshake after {}s",
+                                settings.handshake_timeout.as_secs()
+                            );
+
+                            break Ok(());
+                        }
+                    }
+                }
+                (_, idle_timeout) => {
+                    let idle_timeout = idle_timeout.unwrap_or(MAINTENANCE_IDLE_TIMEOUT);
+
+                    match tokio::time::timeout(idle_timeout, read).await {
+                        Ok(n) => n?,
+                        Err(_) => {
+                            info!(
+                                state = ?connection_state,
+                                "Closing connection idle for {}s",
+                                idle_timeout.as_secs()
+                            );
+
+                            break Ok(());
+                        }
+                    }
+                }
+            };
+
+            if n == 0 {
+                break Ok(());
+            }
+
+            'parse_packets: loop {
+                if buf.is_empty() {
+                    break 'parse_packets;
+                }
+
+                if connection_state == ConnectionState::Handshaking
+                    && buf[0] == LEGACY_PING_PACKET_ID
+                {
+                    let (online_players, max_players) = (
+                        settings.online_players.unwrap_or(0),
+                        settings.max_players.unwrap_or(0),
+                    );
+                    // Legacy clients cannot read a chat component, only formatting codes
+                    let motd = config.borrow().motd.component().to_legacy_text();
+
+                    // 1.4 to 1.6 follow the ping with 0x01 and understand the richer format
+                    let reason = if buf.get(1) == Some(&0x01) {
+                        let version_name = &settings.version_name;
+
+                        format!(
+                            "\u{a7}1\0{LEGACY_PING_PROTOCOL_VERSION}\0{version_name}\0{motd}\0{online_players}\0{max_players}"
+                        )
+                    } else {
+                        format!("{motd}\u{a7}{online_players}\u{a7}{max_players}")
+                    };
+
+                    debug!("Answering legacy server list ping");
+
+                    settings.metrics.record_status_ping();
+
+                    socket.write_all(&write_legacy_kick(&reason)).await?;
+
+                    return Ok(());
+                }
+
+                // No modern handshake is short enough to have 0x02 as its length
+                if connection_state == ConnectionState::Handshaking
+                    && buf[0] == LEGACY_LOGIN_PACKET_ID
+                {
+                    info!("Kicking pre-1.7 client during maintenance");
+
+                    settings.metrics.record_blocked_login();
+
+                    #[cfg(feature = "webhook")]
+                    if let Some(webhook) = &settings.webhook {
+                        webhook.login_blocked(None, peer.ip());
+                    }
+
+                    let reason = settings.blocked_login_reason();
+
+                    if !settings.kick_delay.is_zero() {
+                        tokio::time::sleep(settings.kick_delay).await;
+                    }
+
+                    // Legacy clients show the reason as is, section sign formatting included
+                    socket.write_all(&write_legacy_kick(reason)).await?;
+
+                    return Ok(());
+                }
+
+                let next = next_frame(&mut buf, PACKET_MAX_SIZE);
+
+                let (packet_length_field_length, frame) = match next {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break 'parse_packets,
+                    Err(FrameError::InvalidLength) => {
+                        warn!(state = ?connection_state, "Closing connection on an invalid packet length");
+
+                        return Ok(());
+                    }
+                    Err(FrameError::Oversized(packet_length)) => {
+                        warn!(state = ?connection_state, packet_length, "Closing connection on an oversized packet length");
+
+                        return Ok(());
+                    }
+                };
+
+                let packet_buf = &frame[packet_length_field_length..];
+
+                if matches!(
+                    connection_state,
+                    ConnectionState::Handshaking | ConnectionState::Login
+                ) {
+                    consumed.extend_from_slice(&frame);
+                }
+
+                let (trailing_data, packet) =
+                    match parse_packet(packet_buf, connection_state, protocol_version) {
+                        Ok(parsed) => parsed,
+                        Err(why) => {
+                            let packet_id = parse_varint(packet_buf).map_or(-1, |(_, id)| id);
+
+                            warn!(
+                                state = ?connection_state,
+                                "Closing connection on packet {packet_id:#04x}: {}",
+                                describe_parse_error(&why)
+                            );
+
+                            return Ok(());
+                        }
+                    };
+
+                // The frame length bounds the packet, so anything left over belongs to
+                // fields the proxy does not know about rather than to the next packet
+                if !trailing_data.is_empty() {
+                    warn!(
+                        state = ?connection_state,
+                        "Ignoring {} trailing bytes after the packet",
+                        trailing_data.len()
+                    );
+                }
+
+                match packet {
+                    ServerboundPacket::Handshake {
+                        protocol_version: packet_protocol_version,
+                        server_address,
+                        mod_loader,
+                        server_port,
+                        next_state,
+                    } => {
+                        protocol_version = Some(packet_protocol_version);
+
+                        let hostname = hostname::normalize_hostname(
+                            &server_address,
+                            settings.hostname_normalization,
+                        );
+
+                        info!(
+                            protocol = packet_protocol_version,
+                            next_state, mod_loader, "Handshake for {hostname}:{server_port}"
+                        );
+
+                        settings
+                            .metrics
+                            .record_connection(settings.version_bucket(packet_protocol_version));
+                        settings.metrics.record_host_connection(&hostname);
+
+                        connection_state = match next_state {
+                            1 => ConnectionState::Status,
+                            // Transfers, since 1.20.5, are logins sent over by another server
+                            2 | 3 => ConnectionState::Login,
+                            _ => {
+                                warn!("Invalid next state: {}", next_state);
+                                break 'parse_packets;
+                            }
+                        };
+                    }
+                    ServerboundPacket::StatusRequest => {
+                        settings.metrics.record_status_ping();
+
+                        let protocol_version = protocol_version.unwrap();
+
+                        let forwarded_players = if settings.forward_status {
+                            settings.backend_players(&backend, protocol_version).await
+                        } else {
+                            None
+                        };
+
+                        let players = settings.maintenance_players(forwarded_players);
+
+                        let json_response = settings.status_json(
+                            &config.borrow().motd,
+                            protocol_version,
+                            players,
+                        )?;
+
+                        let src = write_packet(ClientboundPacket::StatusResponse { json_response });
+
+                        socket.writable().await?;
+
+                        socket.write_all(&src).await?;
+                    }
+                    ServerboundPacket::PingRequest { payload } => {
+                        let src = write_packet(ClientboundPacket::PingResponse { payload });
+
+                        socket.writable().await?;
+
+                        socket.write_all(&src).await?;
+                    }
+                    ServerboundPacket::LoginStart { username, uuid } => {
+                        if settings.is_whitelisted(&username) {
+                            info!("Letting whitelisted {username} through");
+
+                            // Whatever followed the login start was read but not parsed yet
+                            consumed.extend_from_slice(&buf);
+
+                            return hand_over_to_server(
+                                socket, peer, &consumed, &backend, &settings,
+                            )
+                            .await;
+                        }
+
+                        match uuid {
+                            Some(uuid) => info!(
+                                uuid = format_uuid(uuid),
+                                "Kicking {username} during maintenance"
+                            ),
+                            None => info!("Kicking {username} during maintenance"),
+                        }
+
+                        settings.metrics.record_blocked_login();
+
+                        #[cfg(feature = "webhook")]
+                        if let Some(webhook) = &settings.webhook {
+                            webhook.login_blocked(Some(&username), peer.ip());
+                        }
+
+                        if let Some(protocol_version) = protocol_version {
+                            settings
+                                .metrics
+                                .record_login_attempt(settings.version_bucket(protocol_version));
+                        }
+
+                        let reason = settings.blocked_login_reason();
+
+                        if !settings.kick_delay.is_zero() {
+                            tokio::time::sleep(settings.kick_delay).await;
+                        }
+
+                        socket.write_all(&login_disconnect_packet(reason)).await?;
+                    }
+                    ServerboundPacket::LoginAcknowledged => {
+                        connection_state = ConnectionState::Configuration;
+
+                        debug!(state = ?connection_state, "Login acknowledged");
+                    }
+                    ServerboundPacket::Configuration { packet_id } => {
+                        debug!(state = ?connection_state, "Ignoring configuration packet {packet_id:#04x}");
+                    }
+                }
+            }
+
+            // Reading into a full buffer would look like the end of the stream
+            buf.reserve(INITIAL_BUFFER_SIZE);
+        }
+    }
+}
+
+pub(crate) struct ChannelConfig {
+    pub is_proxy: bool,
+    pub motd: Motd,
+}
+
+fn apply_control_command(
+    command: ControlCommand,
+    tx: &Sender<ChannelConfig>,
+    kick_tx: &broadcast::Sender<()>,
+) -> ControlResponse {
+    match command {
+        ControlCommand::Authenticate { .. } => ControlResponse::ok("Authenticated"),
+        ControlCommand::SetProxy { enabled } => {
+            tx.send_modify(|config| config.is_proxy = enabled);
+
+            info!("Proxy flag set to {enabled}");
+
+            ControlResponse::ok(format!("Proxy flag set to {enabled}"))
+        }
+        ControlCommand::SetMotd { motd } => {
+            let motd = match motd.parse::<Motd>() {
+                Ok(motd) => motd,
+                Err(why) => return ControlResponse::error(why.to_string()),
+            };
+
+            info!("MOTD set to {:?}", motd.to_string());
+
+            tx.send_modify(|config| config.motd = motd);
+
+            ControlResponse::ok("MOTD updated")
+        }
+        ControlCommand::GetStatus => {
+            let config = tx.borrow();
+
+            ControlResponse {
+                state: Some(ProxyState {
+                    proxying: config.is_proxy,
+                    motd: config.motd.to_string(),
+                }),
+                ..ControlResponse::ok("Current state")
+            }
+        }
+        ControlCommand::KickAll => {
+            // Every client connection holds a receiver
+            let kicked = kick_tx.send(()).unwrap_or(0);
+
+            info!("Kicked {kicked} connections");
+
+            ControlResponse::ok(format!("Kicked {kicked} connections"))
+        }
+    }
+}
+
+async fn process_control_socket(
+    mut socket: Box<dyn ControlStream>,
+    tx: Sender<ChannelConfig>,
+    kick_tx: broadcast::Sender<()>,
+    control_token: Option<&str>,
+) -> anyhow::Result<()> {
+    if let Some(expected) = control_token {
+        let authenticated = match control::read_frame(&mut socket).await {
+            Ok(Some(ControlCommand::Authenticate { token })) => {
+                control::tokens_match(&token, expected)
+            }
+            Ok(None) => return Ok(()),
+            _ => false,
+        };
+
+        if !authenticated {
+            warn!("Rejected control connection with a wrong or missing token");
+
+            let response = ControlResponse::error("Wrong or missing control token");
+            return control::write_frame(&mut socket, &response).await;
+        }
+
+        control::write_frame(&mut socket, &ControlResponse::ok("Authenticated")).await?;
+    }
+
+    loop {
+        let payload = match control::read_raw_frame(&mut socket).await {
+            Ok(Some(payload)) => payload,
+            Ok(None) => return Ok(()),
+            Err(why) => {
+                // The rest of the frame is left unread, so the connection cannot go on
+                let response = ControlResponse::error(format!("Invalid frame: {why}"));
+                let _ = control::write_frame(&mut socket, &response).await;
+
+                return Err(why);
+            }
+        };
+
+        let response = match serde_json::from_slice::<ControlCommand>(&payload) {
+            Ok(command) => apply_control_command(command, &tx, &kick_tx),
+            Err(why) => ControlResponse::error(format!("Invalid command: {why}")),
+        };
+
+        control::write_frame(&mut socket, &response).await?;
+    }
+}
+
+/// Resolves on Ctrl-C, or on the SIGTERM service managers stop processes with
+pub(crate) async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(why) => {
+                warn!("Unable to listen for SIGTERM: {why}");
+                std::future::pending().await
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if let Err(why) = result {
+                warn!("Unable to listen for Ctrl-C: {why}");
+                std::future::pending().await
+            }
+        }
+        _ = terminate => {}
+    }
+}
+
+/// Runs the proxy until `shutdown` resolves, then lets open connections finish
+pub(crate) async fn start_proxy(
+    args: &args::ProxyCommandArgs,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let (tx, mut rx) = tokio::sync::watch::channel(ChannelConfig {
+        is_proxy: true,
+        motd: args.motd.clone(),
+    });
+    let (kick_tx, _) = broadcast::channel(1);
+
+    let backend = Arc::new(Backend::new(args.server_address.clone(), args.server_port));
+    let mut should_proxy = true;
+
+    let limiter = Arc::new(ConnectionLimiter::new(
+        args.max_conns,
+        args.max_conns_per_ip,
+    ));
+
+    let settings = Arc::new(ProxyConfig::from_args(args)?);
+
+    settings.metrics.set_proxy_enabled(should_proxy);
+
+    // Ports without a server of their own share the default one
+    let mut port_backends = Vec::with_capacity(args.proxy_port.len());
+
+    for listen_port in &args.proxy_port {
+        if port_backends
+            .iter()
+            .any(|(port, _): &(u16, Arc<Backend>)| *port == listen_port.port)
+        {
+            anyhow::bail!("Port {} is given more than once", listen_port.port);
+        }
+
+        let port_backend = match (&listen_port.server_address, listen_port.server_port) {
+            (None, None) => backend.clone(),
+            (address, port) => Arc::new(Backend::new(
+                address
+                    .clone()
+                    .unwrap_or_else(|| args.server_address.clone()),
+                port.unwrap_or(args.server_port),
+            )),
+        };
+
+        port_backends.push((listen_port.port, port_backend));
+    }
+
+    for (_, port_backend) in &port_backends {
+        if let Err(why) = health::probe_backend(port_backend).await {
+            // The server may legitimately be down during maintenance, but the proxy
+            // always starts in passthrough mode, so an unreachable one is most likely a typo.
+            if args.fail_fast {
+                anyhow::bail!("Server {port_backend} is unreachable: {why}");
+            }
+
+            warn!("Server {port_backend} is unreachable: {why}");
+        }
+    }
+
+    if let Some(health_interval) = args.health_interval {
+        tokio::spawn(health::monitor_backend(
+            backend.clone(),
+            Duration::from_secs(health_interval),
+            tx.clone(),
+        ));
+    }
+
+    if let Some(metrics_addr) = &args.metrics_addr {
+        let metrics_listener = TcpListener::bind(metrics_addr).await?;
+        let metrics = settings.metrics.clone();
+
+        tokio::spawn(async move {
+            if let Err(why) = metrics::serve_metrics(metrics_listener, metrics).await {
+                error!("Metrics server stopped: {}", why);
+            }
+        });
+    }
+
+    // Several listeners may share the port, so IPv6 ones must leave IPv4 to the others
+    let only_v6 = args.proxy_address.len() > 1;
+    let mut listeners = Vec::with_capacity(args.proxy_address.len() * port_backends.len());
+    let mut listener_backends = Vec::with_capacity(listeners.capacity());
+
+    for (proxy_port, port_backend) in &port_backends {
+        for proxy_address in &args.proxy_address {
+            let listener = listen::bind_listener(proxy_address, *proxy_port, only_v6)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Unable to listen on {}",
+                        hostname::format_socket_address(proxy_address, *proxy_port)
+                    )
+                })?;
+
+            info!("Listening on {} for {port_backend}", listener.local_addr()?);
+
+            listeners.push(listener);
+            listener_backends.push(port_backend.clone());
+        }
+    }
+    let control_listener = ControlListener::bind(&args.socket).await?;
+
+    let connections = TaskTracker::new();
+    let mut next_listener = 0;
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = rx.changed() => {
+                let is_proxy = rx.borrow().is_proxy;
+
+                #[cfg(feature = "webhook")]
+                // Also woken up by MOTD changes, which are not worth a notification
+                if let (Some(webhook), true) = (&settings.webhook, is_proxy != should_proxy) {
+                    webhook.maintenance_toggled(!is_proxy);
+                }
+
+                should_proxy = is_proxy;
+                settings.metrics.set_proxy_enabled(should_proxy);
+            },
+            accepted_socket = listen::accept_any(&listeners, &mut next_listener) => {
+                if let Ok((index, socket, peer)) = accepted_socket {
+                    let Some(connection_guard) = limiter.try_acquire(peer.ip()) else {
+                        warn!(%peer, "Dropping connection, too many connections");
+                        continue;
+                    };
+
+                    let mut kick_rx = kick_tx.subscribe();
+                    let config = rx.clone();
+                    let backend = listener_backends[index].clone();
+                    let settings = settings.clone();
+
+                    debug!(%peer, "Accepted connection");
+
+                    let span = info_span!("connection", %peer);
+
+                    connections.spawn(async move {
+                        // Kicked or not, the slot is freed once the connection is gone
+                        let _connection_guard = connection_guard;
+
+                        tokio::select! {
+                            result = process_socket(socket, peer, should_proxy, config, backend, settings) => {
+                                if let Err(why) = result {
+                                    warn!("Error: {}", why);
+                                }
+                            }
+                            _ = kick_rx.recv() => {
+                                info!("Kicked");
+                            }
+                        }
+                    }.instrument(span));
+                } else {
+                    anyhow::bail!("Error accepting connection");
+                }
+            }
+            accepted_socket = control_listener.accept() => {
+                if let Ok(socket) = accepted_socket {
+                    debug!("Accepted control connection");
+
+                    let tx = tx.clone();
+                    let kick_tx = kick_tx.clone();
+                    let settings = settings.clone();
+
+                    tokio::spawn(async move {
+                        if let Err(why) = process_control_socket(socket, tx, kick_tx, settings.control_token.as_deref()).await {
+                            warn!("Control connection error: {}", why);
+                        }
+                    });
+                } else {
+                    anyhow::bail!("Error accepting connection");
+                }
+            }
+        }
+    }
+
+    // Stop accepting connections, which also removes a Unix control socket
+    drop(listeners);
+    drop(control_listener);
+
+    connections.close();
+
+    info!("Shutting down with {} open connections", connections.len());
+
+    if let Some(grace_period) = args.shutdown_grace_period {
+        let _ = tokio::time::timeout(Duration::from_secs(grace_period), connections.wait()).await;
+    }
+
+    if !connections.is_empty() {
+        info!("Closing {} remaining connections", connections.len());
+
+        let _ = kick_tx.send(());
+        connections.wait().await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use clap::Parser;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::{mpsc, oneshot},
+        task::JoinHandle,
+    };
+
+    use super::*;
+    use crate::status::ServerStatus;
+
+    /// Handshake of a 1.21 client for `localhost:25565` asking for the status,
+    /// followed by the status request, as sent on the wire
+    const STATUS_HANDSHAKE: &[u8] = &[
+        0x10, 0x00, 0xff, 0x05, 0x09, b'l', b'o', b'c', b'a', b'l', b'h', b'o', b's', b't', 0x63,
+        0xdd, 0x01, 0x01, 0x00,
+    ];
+
+    /// Hand-built handshake in the style of a Forge 1.20.1 client, for
+    /// `mc.example.com:25565` with the FML3 marker, followed by the status request
+    const FORGE_STATUS_HANDSHAKE: &[u8] = &[
+        0x1b, 0x00, 0xfb, 0x05, 0x14, b'm', b'c', b'.', b'e', b'x', b'a', b'm', b'p', b'l', b'e',
+        b'.', b'c', b'o', b'm', 0x00, b'F', b'M', b'L', b'3', 0x00, 0x63, 0xdd, 0x01, 0x01, 0x00,
+    ];
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// 1.21 protocol, which sends the player's UUID in Login Start
+    const TEST_PROTOCOL: i32 = 767;
+
+    /// Frames a packet the way a client sends it
+    fn packet(packet_id: i32, content: &[u8]) -> Vec<u8> {
+        let mut packet_buf = BytesMut::new();
+
+        write_varint(packet_id, &mut packet_buf);
+        packet_buf.put(content);
+
+        frame_packet(packet_buf).to_vec()
+    }
+
+    fn handshake(protocol_version: i32, server_address: &str, next_state: i32) -> Vec<u8> {
+        let mut content = BytesMut::new();
+
+        write_varint(protocol_version, &mut content);
+        write_varint(server_address.len() as i32, &mut content);
+        content.put(server_address.as_bytes());
+        content.put_u16(25565);
+        write_varint(next_state, &mut content);
+
+        packet(0x00, &content)
+    }
+
+    fn login_start(username: &str) -> Vec<u8> {
+        let mut content = BytesMut::new();
+
+        write_varint(username.len() as i32, &mut content);
+        content.put(username.as_bytes());
+        content.put_u128(0x0123456789abcdef0123456789abcdef);
+
+        packet(0x00, &content)
+    }
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[test]
+    fn round_trips_varints() {
+        for (value, length) in [(0, 1), (2097151, 3), (-1, 5), (i32::MIN, 5)] {
+            let mut buf = BytesMut::new();
+
+            assert_eq!(write_varint(value, &mut buf), length);
+            assert_eq!(buf.len(), length);
+            assert_eq!(parse_varint(&buf), Ok((&[][..], value)));
+        }
+    }
+
+    #[test]
+    fn rejects_overlong_strings() {
+        let mut input = BytesMut::new();
+        write_varint(17, &mut input);
+        input.put(&b"ThisNameIsTooLong"[..]);
+
+        assert_eq!(
+            parse_string(16, &input),
+            Err(nom::Err::Failure(nom::error::Error::new(
+                &input[..],
+                nom::error::ErrorKind::TooLarge
+            )))
+        );
+
+        // Rejected from the length alone, without waiting for the string itself
+        let mut input = BytesMut::new();
+        write_varint(16 * 3 + 1, &mut input);
+
+        assert!(matches!(
+            parse_string(16, &input),
+            Err(nom::Err::Failure(nom::error::Error {
+                code: nom::error::ErrorKind::TooLarge,
+                ..
+            }))
+        ));
+
+        // Multi-byte characters count once
+        let mut input = BytesMut::new();
+        write_varint(48, &mut input);
+        input.put("\u{20ac}".repeat(16).as_bytes());
+
+        assert_eq!(
+            parse_string(16, &input),
+            Ok((&[][..], "\u{20ac}".repeat(16)))
+        );
+    }
+
+    #[test]
+    fn splits_forge_marker_off_handshake() {
+        let handshake = &FORGE_STATUS_HANDSHAKE[1..28];
+
+        let Ok((rest, packet)) = parse_packet(handshake, ConnectionState::Handshaking, None) else {
+            panic!("Forge handshake rejected");
+        };
+
+        assert!(rest.is_empty());
+        assert!(matches!(
+            packet,
+            ServerboundPacket::Handshake {
+                protocol_version: 763,
+                server_address,
+                mod_loader: Some(mod_loader),
+                server_port: 25565,
+                next_state: 1,
+            } if server_address == "mc.example.com" && mod_loader == "FML3"
+        ));
+    }
+
+    #[tokio::test]
+    async fn tells_read_and_write_errors_apart() {
+        let forwarded = AtomicU64::new(0);
+
+        let reset = io::Error::other("reset by client");
+        let failing_reader =
+            tokio_util::io::StreamReader::new(tokio_stream::iter([Err::<bytes::Bytes, _>(reset)]));
+        let result = forward(failing_reader, tokio::io::sink(), &forwarded).await;
+        assert!(matches!(result, Err(ForwardError::Read(_))));
+
+        let (closed_writer, _) = tokio::io::duplex(64);
+        let result = forward(&b"hello"[..], closed_writer, &forwarded).await;
+        assert!(matches!(result, Err(ForwardError::Write(_))));
+        assert_eq!(forwarded.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn keeps_status_ping_buffer_small() {
+        let mut ping = BytesMut::new();
+        ping.put_u8(0x01);
+        ping.put_i64(0x1234);
+
+        let traffic = [STATUS_HANDSHAKE, &frame_packet(ping)].concat();
+        let mut buf = BytesMut::with_capacity(INITIAL_BUFFER_SIZE);
+        let mut frames = 0;
+
+        // Fed the way the connection loop reads, a byte at a time at worst
+        for chunk_size in [1, 7, traffic.len()] {
+            for chunk in traffic.chunks(chunk_size) {
+                buf.reserve(INITIAL_BUFFER_SIZE);
+                buf.put(chunk);
+
+                while let Ok(Some(_)) = next_frame(&mut buf, PACKET_MAX_SIZE) {
+                    frames += 1;
+                }
+
+                assert!(buf.capacity() <= 4096, "{} bytes", buf.capacity());
+            }
+        }
+
+        assert_eq!(frames, 9);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn parses_player_samples() {
+        for sample in [
+            "Steve:0123456789abcdef0123456789abcdef",
+            "Steve:01234567-89ab-cdef-0123-456789abcdef",
+        ] {
+            let sample = sample.parse::<PlayerSample>().unwrap();
+
+            assert_eq!(sample.name, "Steve");
+            assert_eq!(sample.id, "01234567-89ab-cdef-0123-456789abcdef");
+        }
+
+        // Only the last colon separates the UUID
+        let sample = "Back at 18:00:0123456789abcdef0123456789abcdef"
+            .parse::<PlayerSample>()
+            .unwrap();
+        assert_eq!(sample.name, "Back at 18:00");
+
+        for invalid in [
+            "Steve",
+            "Steve:0123456789abcdef",
+            "Steve:0123456789abcdef0123456789abcdeg",
+            "Steve:01234567-89ab-cdef-0123_456789abcdef",
+            "Steve:0123456789abcdef0123456789abcdef0000",
+        ] {
+            assert!(invalid.parse::<PlayerSample>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn rejects_lengths_longer_than_three_bytes() {
+        // 2^28 - 1 takes a fourth byte, so it fails as an invalid length before the size check
+        let mut buf = BytesMut::from(&[0xff, 0xff, 0xff, 0x7f][..]);
+        assert!(matches!(
+            next_frame(&mut buf, PACKET_MAX_SIZE),
+            Err(FrameError::InvalidLength)
+        ));
+
+        // The largest 3 byte length is allowed, and waited for
+        let mut buf = BytesMut::from(&[0xff, 0xff, 0x7f, 0x00][..]);
+        assert!(matches!(next_frame(&mut buf, PACKET_MAX_SIZE), Ok(None)));
+        assert!(buf.capacity() >= 3 + PACKET_MAX_SIZE);
+    }
+
+    #[test]
+    fn rejects_lengths_over_the_limit() {
+        let mut buf = BytesMut::from(&[0x05, 0x00, 0x01, 0x02, 0x03, 0x04][..]);
+        assert!(matches!(
+            next_frame(&mut buf, 4),
+            Err(FrameError::Oversized(5))
+        ));
+
+        // Rejected before the rest of the packet arrives
+        let mut buf = BytesMut::from(&[0x80, 0x01][..]);
+        assert!(matches!(
+            next_frame(&mut buf, 127),
+            Err(FrameError::Oversized(128))
+        ));
+
+        let mut buf = BytesMut::from(&[0x05, 0x00, 0x01, 0x02, 0x03, 0x04][..]);
+        assert!(matches!(next_frame(&mut buf, 5), Ok(Some((1, _)))));
+        assert!(buf.is_empty());
+    }
+
+    fn parse_proxy_args(
+        arguments: &[&str],
+        extra_arguments: &[&str],
+    ) -> Box<args::ProxyCommandArgs> {
+        let arguments = ["minecraft-maintenance-proxy", "proxy"]
+            .iter()
+            .chain(arguments)
+            .chain(extra_arguments);
+
+        let args::Commands::Proxy(args) = args::Config::parse_from(arguments).command else {
+            unreachable!()
+        };
+
+        args
+    }
+
+    #[test]
+    fn builds_status_response_without_growing_its_buffer() {
+        let settings = ProxyConfig::from_args(&parse_proxy_args(
+            &[],
+            &[
+                "--max-players",
+                "20",
+                "--player-sample",
+                "Back soon:0123456789abcdef0123456789abcdef",
+            ],
+        ))
+        .unwrap();
+        let motd = r#"{"text":"Back at 18:00","color":"gold"}"#.parse::<Motd>().unwrap();
+
+        let players = settings.maintenance_players(None);
+        let json_response = settings.status_json(&motd, TEST_PROTOCOL, players).unwrap();
+
+        // A buffer that had to grow would have a larger capacity than it started with
+        assert_eq!(json_response.capacity(), settings.status_json_size(&motd));
+
+        let status: ServerStatus = serde_json::from_str(&json_response).unwrap();
+        assert_eq!(status.players.unwrap().max, 20);
+        assert!(json_response.contains(r#""favicon":"data:image/png;base64,"#));
+    }
+
+    /// Fetches the metrics served on `metrics_addr`
+    async fn scrape_metrics(metrics_addr: &str) -> String {
+        let mut socket = TcpStream::connect(metrics_addr).await.unwrap();
+        socket
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        tokio::time::timeout(TEST_TIMEOUT, socket.read_to_string(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+
+        response
+    }
+
+    struct TestProxy {
+        port: u16,
+        control_socket: String,
+        shutdown: oneshot::Sender<()>,
+        task: JoinHandle<anyhow::Result<()>>,
+    }
+
+    impl TestProxy {
+        async fn start(extra_arguments: &[&str]) -> Self {
+            let port = free_port();
+            let control_socket = format!("127.0.0.1:{}", free_port());
+
+            let args = parse_proxy_args(
+                &[
+                    "--proxy-address",
+                    "127.0.0.1",
+                    "--proxy-port",
+                    &port.to_string(),
+                    "--socket",
+                    &control_socket,
+                ],
+                extra_arguments,
+            );
+
+            let (shutdown, shutdown_rx) = oneshot::channel();
+
+            let task = tokio::spawn(async move {
+                start_proxy(&args, async {
+                    let _ = shutdown_rx.await;
+                })
+                .await
+            });
+
+            // Both listeners are bound before the accept loop starts
+            tokio::time::timeout(TEST_TIMEOUT, async {
+                while TcpStream::connect(&control_socket).await.is_err() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("proxy did not start");
+
+            Self {
+                port,
+                control_socket,
+                shutdown,
+                task,
+            }
+        }
+
+        async fn set_proxy(&self, enabled: bool) {
+            let mut socket = control::connect(&self.control_socket).await.unwrap();
+
+            control::write_frame(&mut socket, &ControlCommand::SetProxy { enabled })
+                .await
+                .unwrap();
+
+            let response: ControlResponse =
+                control::read_frame(&mut socket).await.unwrap().unwrap();
+
+            assert!(response.ok, "{}", response.message);
+        }
+
+        async fn connect(&self) -> TcpStream {
+            TcpStream::connect(("127.0.0.1", self.port)).await.unwrap()
+        }
+
+        async fn stop(self) {
+            self.shutdown.send(()).unwrap();
+
+            tokio::time::timeout(TEST_TIMEOUT, self.task)
+                .await
+                .expect("proxy did not shut down")
+                .unwrap()
+                .unwrap();
+        }
+    }
+
+    /// Reads one framed packet, returning its id and content
+    async fn read_packet(socket: &mut TcpStream) -> (i32, Vec<u8>) {
+        let mut buf = Vec::new();
+
+        loop {
+            if let Ok((rest, length)) = parse_varint(&buf) {
+                if rest.len() >= length as usize {
+                    let (content, packet_id) = parse_varint(&rest[..length as usize]).unwrap();
+
+                    return (packet_id, content.to_vec());
+                }
+            }
+
+            let mut chunk = [0; 4096];
+            let n = socket.read(&mut chunk).await.unwrap();
+            assert_ne!(n, 0, "connection closed before a full packet");
+
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    async fn query_maintenance_status(
+        proxy: &TestProxy,
+        request: &[u8],
+        chunk_size: usize,
+    ) -> ServerStatus {
+        let mut socket = proxy.connect().await;
+
+        // Split writes exercise reassembling packets across reads
+        for chunk in request.chunks(chunk_size) {
+            socket.write_all(chunk).await.unwrap();
+            socket.flush().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let (packet_id, content) = tokio::time::timeout(TEST_TIMEOUT, read_packet(&mut socket))
+            .await
+            .expect("no status response");
+
+        assert_eq!(packet_id, 0x00);
+
+        let (_, json_response) = parse_string(32767, &content).unwrap();
+
+        serde_json::from_str(&json_response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn answers_status_during_maintenance() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+
+        proxy.set_proxy(false).await;
+
+        for chunk_size in [STATUS_HANDSHAKE.len(), 1, 3] {
+            let status = query_maintenance_status(&proxy, STATUS_HANDSHAKE, chunk_size).await;
+
+            assert_eq!(
+                crate::chat::plain_text(&status.description.unwrap()),
+                "Server is currently in maintenance"
+            );
+
+            let version = status.version.unwrap();
+            assert_eq!(version.name, "1.7.10");
+            assert_eq!(version.protocol, 767);
+        }
+
+        proxy.stop().await;
+    }
+
+    /// Reads the reason of the login disconnect the proxy answers a login with
+    async fn read_login_disconnect(socket: &mut TcpStream) -> String {
+        let (packet_id, content) = tokio::time::timeout(TEST_TIMEOUT, read_packet(socket))
+            .await
+            .expect("no login disconnect");
+
+        assert_eq!(packet_id, 0x00);
+
+        let (_, reason) = parse_string(262144, &content).unwrap();
+        let reason: serde_json::Value = serde_json::from_str(&reason).unwrap();
+
+        reason["text"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn reports_configured_version() {
+        let proxy = TestProxy::start(&[
+            "--server-port",
+            &free_port().to_string(),
+            "--version-name",
+            "Maintenance",
+            "--version-protocol",
+            "-1",
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        let version = query_maintenance_status(&proxy, STATUS_HANDSHAKE, STATUS_HANDSHAKE.len())
+            .await
+            .version
+            .unwrap();
+
+        assert_eq!(version.name, "Maintenance");
+        assert_eq!(version.protocol, -1);
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn names_version_after_client_protocol() {
+        let proxy = TestProxy::start(&[
+            "--server-port",
+            &free_port().to_string(),
+            "--per-version-name",
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        for (protocol_version, name) in [(47, "1.8.9"), (767, "1.21.1"), (12345, "1.7.10")] {
+            let request = [
+                handshake(protocol_version, "localhost", 1),
+                packet(0x00, &[]),
+            ]
+            .concat();
+
+            let version = query_maintenance_status(&proxy, &request, request.len())
+                .await
+                .version
+                .unwrap();
+
+            assert_eq!(version.name, name);
+            assert_eq!(version.protocol, protocol_version);
+        }
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn reports_configured_players() {
+        let proxy = TestProxy::start(&[
+            "--server-port",
+            &free_port().to_string(),
+            "--max-players",
+            "20",
+            "--online-players",
+            "3",
+            "--player-sample",
+            "Back soon:0123456789abcdef0123456789abcdef",
+            "--player-sample",
+            "Admins only:fedcba98-7654-3210-fedc-ba9876543210",
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        let players = query_maintenance_status(&proxy, STATUS_HANDSHAKE, STATUS_HANDSHAKE.len())
+            .await
+            .players
+            .unwrap();
+
+        assert_eq!((players.max, players.online), (20, 3));
+        assert_eq!(
+            players
+                .sample
+                .iter()
+                .map(|sample| (sample.name.as_str(), sample.id.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("Back soon", "01234567-89ab-cdef-0123-456789abcdef"),
+                ("Admins only", "fedcba98-7654-3210-fedc-ba9876543210"),
+            ]
+        );
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn answers_status_with_updated_motd() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+
+        proxy.set_proxy(false).await;
+
+        // Answered once with the starting MOTD, so a stale cached response would show
+        query_maintenance_status(&proxy, STATUS_HANDSHAKE, STATUS_HANDSHAKE.len()).await;
+
+        let mut socket = control::connect(&proxy.control_socket).await.unwrap();
+        control::write_frame(
+            &mut socket,
+            &ControlCommand::SetMotd {
+                motd: r#"{"text":"Back at 18:00","color":"gold"}"#.to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let response: ControlResponse = control::read_frame(&mut socket).await.unwrap().unwrap();
+        assert!(response.ok, "{}", response.message);
+
+        let status =
+            query_maintenance_status(&proxy, STATUS_HANDSHAKE, STATUS_HANDSHAKE.len()).await;
+
+        assert_eq!(
+            status.description.unwrap(),
+            serde_json::json!({ "text": "Back at 18:00", "color": "gold" })
+        );
+
+        drop(socket);
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn answers_forge_status_during_maintenance() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+
+        proxy.set_proxy(false).await;
+
+        let status =
+            query_maintenance_status(&proxy, FORGE_STATUS_HANDSHAKE, FORGE_STATUS_HANDSHAKE.len())
+                .await;
+
+        assert_eq!(status.version.unwrap().protocol, 763);
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn reassembles_length_split_across_reads() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+
+        proxy.set_proxy(false).await;
+
+        // A hostname this long takes the handshake length to two bytes
+        let request = [
+            handshake(TEST_PROTOCOL, &"a".repeat(200), 1),
+            packet(0x00, &[]),
+        ]
+        .concat();
+        assert!(request[0] & 0x80 != 0);
+
+        let status = query_maintenance_status(&proxy, &request, 1).await;
+
+        assert_eq!(status.version.unwrap().protocol, TEST_PROTOCOL);
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn closes_idle_maintenance_connection() {
+        let proxy = TestProxy::start(&[
+            "--server-port",
+            &free_port().to_string(),
+            "--idle-timeout",
+            "1",
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        let mut socket = proxy.connect().await;
+
+        socket
+            .write_all(&handshake(TEST_PROTOCOL, "localhost", 1))
+            .await
+            .unwrap();
+
+        let started = Instant::now();
+        let mut answer = [0; 16];
+        let n = tokio::time::timeout(TEST_TIMEOUT, socket.read(&mut answer))
+            .await
+            .expect("idle connection left open")
+            .unwrap();
+
+        assert_eq!(n, 0);
+        assert!(started.elapsed() >= Duration::from_millis(900));
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn kicks_each_client_generation_in_its_format() {
+        let proxy = TestProxy::start(&[
+            "--server-port",
+            &free_port().to_string(),
+            "--disconnect-message",
+            "Back soon",
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        // Pre-1.7 login: packet 0x02, protocol 78, then the username as UTF-16
+        let mut socket = proxy.connect().await;
+        socket
+            .write_all(&[
+                0x02, 0x4e, 0x00, 0x05, 0x00, b'S', 0x00, b't', 0x00, b'e', 0x00, b'v', 0x00, b'e',
+            ])
+            .await
+            .unwrap();
+
+        let mut kick = Vec::new();
+        tokio::time::timeout(TEST_TIMEOUT, socket.read_to_end(&mut kick))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let reason = "Back soon".encode_utf16().flat_map(u16::to_be_bytes);
+        assert_eq!(
+            kick,
+            [0xff, 0x00, 0x09]
+                .into_iter()
+                .chain(reason)
+                .collect::<Vec<_>>()
+        );
+
+        // 1.21 login
+        let mut socket = proxy.connect().await;
+        socket
+            .write_all(&handshake(TEST_PROTOCOL, "localhost", 2))
+            .await
+            .unwrap();
+        socket.write_all(&login_start("Steve")).await.unwrap();
+
+        assert_eq!(read_login_disconnect(&mut socket).await, "Back soon");
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn kicks_transferred_players_during_maintenance() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+
+        proxy.set_proxy(false).await;
+
+        let mut socket = proxy.connect().await;
+
+        socket
+            .write_all(&handshake(TEST_PROTOCOL, "localhost", 3))
+            .await
+            .unwrap();
+        socket.write_all(&login_start("Steve")).await.unwrap();
+
+        assert_eq!(
+            read_login_disconnect(&mut socket).await,
+            "Server is currently in maintenance"
+        );
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn closes_connection_on_overlong_username() {
+        let (backend_port, mut received_rx) = spawn_backend().await;
+
+        let proxy = TestProxy::start(&[
+            "--server-address",
+            "127.0.0.1",
+            "--server-port",
+            &backend_port.to_string(),
+            "--whitelist",
+            "SeventeenLetters_",
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        let mut socket = proxy.connect().await;
+        socket
+            .write_all(&handshake(TEST_PROTOCOL, "localhost", 2))
+            .await
+            .unwrap();
+        socket
+            .write_all(&login_start("SeventeenLetters_"))
+            .await
+            .unwrap();
+
+        // Neither kicked with a disconnect packet nor replayed to the server
+        let mut answer = Vec::new();
+        tokio::time::timeout(Duration::from_secs(1), socket.read_to_end(&mut answer))
+            .await
+            .expect("connection left open")
+            .unwrap();
+        assert!(answer.is_empty());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), received_rx.recv())
+                .await
+                .is_err()
+        );
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn closes_connection_on_overlong_length_field() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+
+        proxy.set_proxy(false).await;
+
+        let mut socket = proxy.connect().await;
+        socket.write_all(&[0xff, 0xff, 0xff, 0x7f]).await.unwrap();
+
+        // Closed at once, well before the handshake timeout
+        let mut answer = Vec::new();
+        tokio::time::timeout(Duration::from_secs(1), socket.read_to_end(&mut answer))
+            .await
+            .expect("connection left open")
+            .unwrap();
+        assert!(answer.is_empty());
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn delays_maintenance_kick() {
+        let proxy = TestProxy::start(&[
+            "--server-port",
+            &free_port().to_string(),
+            "--kick-delay",
+            "300",
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        let mut socket = proxy.connect().await;
+        let started = Instant::now();
+
+        socket
+            .write_all(&handshake(TEST_PROTOCOL, "localhost", 2))
+            .await
+            .unwrap();
+        socket.write_all(&login_start("Steve")).await.unwrap();
+
+        let reason = read_login_disconnect(&mut socket).await;
+
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert_eq!(reason, "Server is currently in maintenance");
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn counts_connections_per_protocol_version() {
+        let metrics_addr = format!("127.0.0.1:{}", free_port());
+        let proxy = TestProxy::start(&[
+            "--server-port",
+            &free_port().to_string(),
+            "--metrics-addr",
+            &metrics_addr,
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        // Two 1.21 clients, one 1.8 client and two versions missing from the table
+        for protocol_version in [767, 767, 47, 12345, 54321] {
+            let mut socket = proxy.connect().await;
+
+            socket
+                .write_all(&handshake(protocol_version, "localhost", 1))
+                .await
+                .unwrap();
+            socket.write_all(&packet(0x00, &[])).await.unwrap();
+
+            tokio::time::timeout(TEST_TIMEOUT, read_packet(&mut socket))
+                .await
+                .expect("no status response");
+        }
+
+        let response = scrape_metrics(&metrics_addr).await;
+
+        for expected in [
+            "mmp_connections_by_host_total{host=\"localhost\"} 5\n",
+            "mmp_connections_by_protocol_total{protocol=\"47\"} 1\n",
+            "mmp_connections_by_protocol_total{protocol=\"767\"} 2\n",
+            "mmp_connections_by_protocol_total{protocol=\"other\"} 2\n",
+        ] {
+            assert!(response.contains(expected), "{expected:?} in {response}");
+        }
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn kicks_logins_when_the_server_is_unreachable() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+
+        proxy.set_proxy(true).await;
+
+        let mut socket = proxy.connect().await;
+
+        // Answered as soon as the handshake is reassembled across reads
+        for chunk in handshake(TEST_PROTOCOL, "localhost", 2).chunks(3) {
+            socket.write_all(chunk).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        assert_eq!(
+            read_login_disconnect(&mut socket).await,
+            BACKEND_UNAVAILABLE_MESSAGE
+        );
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn fails_fast_on_unreachable_server() {
+        let args = parse_proxy_args(
+            &[
+                "--proxy-address",
+                "127.0.0.1",
+                "--proxy-port",
+                &free_port().to_string(),
+                "--socket",
+                &format!("127.0.0.1:{}", free_port()),
+            ],
+            &[
+                "--server-address",
+                "127.0.0.1",
+                "--server-port",
+                &free_port().to_string(),
+                "--fail-fast",
+            ],
+        );
+
+        let result = tokio::time::timeout(
+            TEST_TIMEOUT,
+            start_proxy(&args, std::future::pending::<()>()),
+        )
+        .await
+        .expect("proxy started against an unreachable server");
+
+        assert!(result.unwrap_err().to_string().contains("is unreachable"));
+    }
+
+    #[tokio::test]
+    async fn answers_invalid_control_command() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+        let mut socket = control::connect(&proxy.control_socket).await.unwrap();
+
+        control::write_frame(&mut socket, &serde_json::json!({ "command": "explode" }))
+            .await
+            .unwrap();
+
+        let response: ControlResponse = control::read_frame(&mut socket).await.unwrap().unwrap();
+        assert!(!response.ok);
+        assert!(response.message.starts_with("Invalid command"));
+
+        // The connection is still usable after the error
+        control::write_frame(&mut socket, &ControlCommand::GetStatus)
+            .await
+            .unwrap();
+
+        let response: ControlResponse = control::read_frame(&mut socket).await.unwrap().unwrap();
+        assert!(response.ok);
+        assert!(response.state.unwrap().proxying);
+
+        drop(socket);
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn moves_to_configuration_after_login_acknowledged() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+
+        proxy.set_proxy(false).await;
+
+        let mut socket = proxy.connect().await;
+
+        socket
+            .write_all(&handshake(TEST_PROTOCOL, "localhost", 2))
+            .await
+            .unwrap();
+        socket.write_all(&login_start("Steve")).await.unwrap();
+
+        read_login_disconnect(&mut socket).await;
+
+        // A configuration packet would be unknown, and close the connection, in the login state
+        socket.write_all(&packet(0x03, &[])).await.unwrap();
+        socket
+            .write_all(&packet(0x02, b"\x0fminecraft:brand"))
+            .await
+            .unwrap();
+
+        let mut answer = [0; 16];
+        let read = tokio::time::timeout(Duration::from_millis(200), socket.read(&mut answer)).await;
+        assert!(
+            read.is_err(),
+            "connection closed in the configuration state"
+        );
+
+        proxy.stop().await;
+    }
+
+    /// Mock server answering every connection's first bytes with a greeting,
+    /// and sending whatever each connection received on the returned channel
+    async fn spawn_backend() -> (u16, mpsc::UnboundedReceiver<Vec<u8>>) {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_port = backend.local_addr().unwrap().port();
+        let (received_tx, received_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = backend.accept().await.unwrap();
+                let received_tx = received_tx.clone();
+
+                tokio::spawn(async move {
+                    let mut received = Vec::new();
+                    let mut chunk = vec![0; 4096];
+
+                    // Until a short pause, so a client's writes arrive as one
+                    while let Ok(Ok(n @ 1..)) =
+                        tokio::time::timeout(Duration::from_millis(100), socket.read(&mut chunk))
+                            .await
+                    {
+                        received.extend_from_slice(&chunk[..n]);
+                    }
+
+                    // The reachability check on startup connects without sending anything
+                    if !received.is_empty() {
+                        socket.write_all(b"hello from the server").await.unwrap();
+                        received_tx.send(received).unwrap();
+                    }
+                });
+            }
+        });
+
+        (backend_port, received_rx)
+    }
+
+    #[tokio::test]
+    async fn answers_oversized_control_frame_before_closing() {
+        let proxy = TestProxy::start(&["--server-port", &free_port().to_string()]).await;
+        let mut socket = control::connect(&proxy.control_socket).await.unwrap();
+
+        socket.write_u32(1024 * 1024).await.unwrap();
+        socket.write_all(b"{\"command\":").await.unwrap();
+
+        let response: ControlResponse = control::read_frame(&mut socket).await.unwrap().unwrap();
+        assert!(!response.ok);
+        assert!(response.message.starts_with("Invalid frame"));
+
+        // Closing with the frame unread may reset the connection rather than end it
+        let mut rest = [0; 16];
+        let read = tokio::time::timeout(TEST_TIMEOUT, socket.read(&mut rest))
+            .await
+            .expect("connection left open");
+        assert!(matches!(read, Ok(0) | Err(_)));
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn counts_connections_per_normalized_hostname() {
+        let metrics_addr = format!("127.0.0.1:{}", free_port());
+        let proxy = TestProxy::start(&[
+            "--server-port",
+            &free_port().to_string(),
+            "--metrics-addr",
+            &metrics_addr,
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        for server_address in [
+            "Play.Example.com.",
+            "play.example.com",
+            "PLAY.example.com:25565",
+            "play.example.com.\0FML3\0",
+            "lobby.example.com",
+        ] {
+            let request = [
+                handshake(TEST_PROTOCOL, server_address, 1),
+                packet(0x00, &[]),
+            ]
+            .concat();
+
+            query_maintenance_status(&proxy, &request, request.len()).await;
+        }
+
+        let response = scrape_metrics(&metrics_addr).await;
+
+        for expected in [
+            "mmp_connections_by_host_total{host=\"lobby.example.com\"} 1\n",
+            "mmp_connections_by_host_total{host=\"play.example.com\"} 4\n",
+        ] {
+            assert!(response.contains(expected), "{expected:?} in {response}");
+        }
+        assert_eq!(
+            response.matches("mmp_connections_by_host_total{").count(),
+            2
+        );
+
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn passes_bytes_through_to_the_server() {
+        let (backend_port, mut received_rx) = spawn_backend().await;
+
+        let proxy = TestProxy::start(&[
+            "--server-address",
+            "127.0.0.1",
+            "--server-port",
+            &backend_port.to_string(),
+        ])
+        .await;
+
+        let mut socket = proxy.connect().await;
+        socket.write_all(b"hello from the client").await.unwrap();
+
+        let received = tokio::time::timeout(TEST_TIMEOUT, received_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, b"hello from the client");
+
+        let mut answer = vec![0; 64];
+        let n = tokio::time::timeout(TEST_TIMEOUT, socket.read(&mut answer))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&answer[..n], b"hello from the server");
+
+        drop(socket);
+        proxy.stop().await;
+    }
+
+    #[tokio::test]
+    async fn replays_login_of_whitelisted_players() {
+        let (backend_port, mut received_rx) = spawn_backend().await;
+
+        let proxy = TestProxy::start(&[
+            "--server-address",
+            "127.0.0.1",
+            "--server-port",
+            &backend_port.to_string(),
+            "--whitelist",
+            "Steve",
+        ])
+        .await;
+
+        proxy.set_proxy(false).await;
+
+        let login = [
+            handshake(TEST_PROTOCOL, "localhost", 2),
+            login_start("steve"),
+        ]
+        .concat();
+
+        let mut socket = proxy.connect().await;
+        socket.write_all(&login).await.unwrap();
+
+        let received = tokio::time::timeout(TEST_TIMEOUT, received_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, login);
+
+        let mut answer = vec![0; 64];
+        let n = tokio::time::timeout(TEST_TIMEOUT, socket.read(&mut answer))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&answer[..n], b"hello from the server");
+
+        drop(socket);
+        proxy.stop().await;
+    }
+}
@@ -0,0 +1,146 @@
+use std::net::{IpAddr, SocketAddr};
+
+use bytes::{BufMut, BytesMut};
+
+use crate::cidr;
+
+/// See https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+const SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Protocol version 2, PROXY command
+const VERSION_COMMAND: u8 = 0x21;
+
+/// Protocol version 2, LOCAL command
+const VERSION_COMMAND_LOCAL: u8 = 0x20;
+
+const UNSPECIFIED: u8 = 0x00;
+
+const TCP_OVER_IPV4: u8 = 0x11;
+const TCP_OVER_IPV6: u8 = 0x21;
+
+/// Encodes the PROXY protocol v2 header announcing a TCP connection from
+/// `source` to `destination`, to be sent before any other byte.
+pub(crate) fn encode_v2_header(source: SocketAddr, destination: SocketAddr) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(SIGNATURE.len() + 4 + 36);
+
+    buf.put_slice(SIGNATURE);
+    buf.put_u8(VERSION_COMMAND);
+
+    match (
+        cidr::canonical_ip(source.ip()),
+        cidr::canonical_ip(destination.ip()),
+    ) {
+        (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+            buf.put_u8(TCP_OVER_IPV4);
+            buf.put_u16(12);
+            buf.put_slice(&source_ip.octets());
+            buf.put_slice(&destination_ip.octets());
+        }
+        // Both addresses must share a family, so mixed pairs use IPv4-mapped IPv6 addresses
+        (source_ip, destination_ip) => {
+            buf.put_u8(TCP_OVER_IPV6);
+            buf.put_u16(36);
+            buf.put_slice(&to_ipv6(source_ip).octets());
+            buf.put_slice(&to_ipv6(destination_ip).octets());
+        }
+    }
+
+    buf.put_u16(source.port());
+    buf.put_u16(destination.port());
+
+    buf
+}
+
+/// Encodes the header for connections the proxy opens on its own behalf, such as status queries
+pub(crate) fn encode_v2_local_header() -> BytesMut {
+    let mut buf = BytesMut::with_capacity(SIGNATURE.len() + 4);
+
+    buf.put_slice(SIGNATURE);
+    buf.put_u8(VERSION_COMMAND_LOCAL);
+    buf.put_u8(UNSPECIFIED);
+    buf.put_u16(0);
+
+    buf
+}
+
+fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNATURE_BYTES: [u8; 12] = [
+        0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a,
+    ];
+
+    fn header(source: &str, destination: &str) -> Vec<u8> {
+        encode_v2_header(source.parse().unwrap(), destination.parse().unwrap()).to_vec()
+    }
+
+    #[test]
+    fn encodes_ipv4_header() {
+        let expected = [
+            &SIGNATURE_BYTES[..],
+            &[0x21, 0x11, 0x00, 0x0c],
+            &[192, 0, 2, 1],
+            &[10, 0, 0, 2],
+            &[0xd4, 0x31, 0x63, 0xdd],
+        ]
+        .concat();
+
+        assert_eq!(header("192.0.2.1:54321", "10.0.0.2:25565"), expected);
+    }
+
+    #[test]
+    fn encodes_ipv6_header() {
+        let expected = [
+            &SIGNATURE_BYTES[..],
+            &[0x21, 0x21, 0x00, 0x24],
+            &[
+                0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+            ],
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01],
+            &[0xd4, 0x31, 0x63, 0xdd],
+        ]
+        .concat();
+
+        assert_eq!(header("[2001:db8::1]:54321", "[::1]:25565"), expected);
+    }
+
+    #[test]
+    fn encodes_mixed_families_as_ipv6() {
+        let expected = [
+            &SIGNATURE_BYTES[..],
+            &[0x21, 0x21, 0x00, 0x24],
+            &[
+                0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+            ],
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 10, 0, 0, 2],
+            &[0xd4, 0x31, 0x63, 0xdd],
+        ]
+        .concat();
+
+        assert_eq!(header("[2001:db8::1]:54321", "10.0.0.2:25565"), expected);
+    }
+
+    #[test]
+    fn encodes_ipv4_mapped_pairs_as_ipv4() {
+        // What a dual-stack listener reports for an IPv4 client
+        assert_eq!(
+            header("[::ffff:192.0.2.1]:54321", "[::ffff:10.0.0.2]:25565"),
+            header("192.0.2.1:54321", "10.0.0.2:25565")
+        );
+    }
+
+    #[test]
+    fn encodes_local_header() {
+        let expected = [&SIGNATURE_BYTES[..], &[0x20, 0x00, 0x00, 0x00]].concat();
+
+        assert_eq!(encode_v2_local_header().to_vec(), expected);
+    }
+}
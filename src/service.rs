@@ -0,0 +1,96 @@
+use std::{ffi::OsString, sync::OnceLock, time::Duration};
+
+use anyhow::Context;
+use tracing::error;
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+};
+
+use crate::{args, proxy};
+
+/// The service entry point takes no context, so the proxy arguments are handed over here
+static SERVICE: OnceLock<(String, args::ProxyCommandArgs)> = OnceLock::new();
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Time the proxy needs to stop once its connections are closed
+const SERVICE_STOP_WAIT_HINT: Duration = Duration::from_secs(5);
+
+/// Runs the proxy as the Windows service installed under `service_name`,
+/// blocking until the service control manager stops it.
+pub(crate) fn run(service_name: String, args: args::ProxyCommandArgs) -> anyhow::Result<()> {
+    SERVICE
+        .set((service_name.clone(), args))
+        .ok()
+        .context("Service already started")?;
+
+    service_dispatcher::start(service_name, ffi_service_main)
+        .context("Unable to reach the service control manager, this flag is only meant for installed services")?;
+
+    Ok(())
+}
+
+fn status(state: ServiceState, exit_code: u32) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted: if state == ServiceState::Running {
+            ServiceControlAccept::STOP
+        } else {
+            ServiceControlAccept::empty()
+        },
+        exit_code: ServiceExitCode::Win32(exit_code),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    }
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(why) = run_service() {
+        error!("Service failed: {why:#}");
+    }
+}
+
+fn run_service() -> anyhow::Result<()> {
+    let (service_name, args) = SERVICE.get().context("Service arguments missing")?;
+
+    let (stop_tx, mut stop_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let status_handle =
+        service_control_handler::register(service_name, move |control| match control {
+            ServiceControl::Stop => {
+                let _ = stop_tx.send(());
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+
+    status_handle.set_service_status(status(ServiceState::Running, 0))?;
+
+    let shutdown = async move {
+        let _ = stop_rx.recv().await;
+
+        // Open connections may take the whole grace period to finish
+        let grace_period = Duration::from_secs(args.shutdown_grace_period.unwrap_or(0));
+        let _ = status_handle.set_service_status(ServiceStatus {
+            wait_hint: grace_period + SERVICE_STOP_WAIT_HINT,
+            ..status(ServiceState::StopPending, 0)
+        });
+    };
+
+    let result = tokio::runtime::Runtime::new()?.block_on(proxy::start_proxy(args, shutdown));
+
+    // Any non-zero code tells the service control manager the service failed
+    let exit_code = if result.is_ok() { 0 } else { 1 };
+    status_handle.set_service_status(status(ServiceState::Stopped, exit_code))?;
+
+    result
+}
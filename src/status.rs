@@ -0,0 +1,105 @@
+use bytes::{BufMut, BytesMut};
+use nom::{bytes::streaming::take, combinator::complete, IResult};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    proxy::{
+        frame_packet, parse_string, parse_varint, write_varint, PlayersResponse, PACKET_MAX_SIZE,
+    },
+    proxy_protocol,
+};
+
+/// See https://wiki.vg/Protocol#Status_Response
+const STATUS_RESPONSE_MAX_LENGTH: usize = 32767;
+
+/// A status response as any server may send it, where every field is optional
+#[derive(Debug, Deserialize)]
+pub(crate) struct ServerStatus {
+    pub(crate) version: Option<ServerVersion>,
+    pub(crate) description: Option<serde_json::Value>,
+    pub(crate) players: Option<PlayersResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ServerVersion {
+    pub(crate) name: String,
+    pub(crate) protocol: i32,
+}
+
+fn parse_status_response(input: &[u8]) -> IResult<&[u8], String> {
+    let (input, packet_length) = parse_varint(input)?;
+
+    let packet_length = usize::try_from(packet_length)
+        .ok()
+        .filter(|packet_length| *packet_length <= PACKET_MAX_SIZE)
+        .ok_or(nom::Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::TooLarge,
+        )))?;
+
+    let (input, packet) = take(packet_length)(input)?;
+
+    // The packet is fully buffered, so running out of input inside it is an error
+    let (packet, packet_id) = complete(parse_varint)(packet)?;
+
+    if packet_id != 0x00 {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            packet,
+            nom::error::ErrorKind::Switch,
+        )));
+    }
+
+    let (_, json_response) =
+        complete(|packet| parse_string(STATUS_RESPONSE_MAX_LENGTH, packet))(packet)?;
+
+    Ok((input, json_response))
+}
+
+/// Performs a handshake and a status request against a server, like the
+/// server list does, and returns the JSON status it answered with.
+pub(crate) async fn query_status(
+    host: &str,
+    port: u16,
+    protocol_version: i32,
+    proxy_protocol: bool,
+) -> anyhow::Result<String> {
+    let mut socket = TcpStream::connect((host, port)).await?;
+
+    if proxy_protocol {
+        socket
+            .write_all(&proxy_protocol::encode_v2_local_header())
+            .await?;
+    }
+
+    let mut handshake = BytesMut::with_capacity(10 + host.len());
+    write_varint(0x00, &mut handshake);
+    write_varint(protocol_version, &mut handshake);
+    write_varint(host.len() as i32, &mut handshake);
+    handshake.put(host.as_bytes());
+    handshake.put_u16(port);
+    write_varint(1, &mut handshake);
+
+    let mut status_request = BytesMut::with_capacity(1);
+    write_varint(0x00, &mut status_request);
+
+    socket.write_all(&frame_packet(handshake)).await?;
+    socket.write_all(&frame_packet(status_request)).await?;
+
+    let mut buf = BytesMut::with_capacity(4096);
+
+    loop {
+        if socket.read_buf(&mut buf).await? == 0 {
+            anyhow::bail!("Server closed the connection before answering the status request");
+        }
+
+        match parse_status_response(&buf) {
+            Ok((_, json_response)) => break Ok(json_response),
+            Err(nom::Err::Incomplete(_)) if buf.len() <= PACKET_MAX_SIZE => continue,
+            Err(_) => anyhow::bail!("Server sent an invalid status response"),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Context;
+
+/// Release names for each protocol number. When several releases share a
+/// protocol number, the latest one is used.
+///
+/// See https://wiki.vg/Protocol_version_numbers
+const BUILTIN_PROTOCOL_VERSIONS: &[(i32, &str)] = &[
+    (4, "1.7.5"),
+    (5, "1.7.10"),
+    (47, "1.8.9"),
+    (107, "1.9"),
+    (108, "1.9.1"),
+    (109, "1.9.2"),
+    (110, "1.9.4"),
+    (210, "1.10.2"),
+    (315, "1.11"),
+    (316, "1.11.2"),
+    (335, "1.12"),
+    (338, "1.12.1"),
+    (340, "1.12.2"),
+    (393, "1.13"),
+    (401, "1.13.1"),
+    (404, "1.13.2"),
+    (477, "1.14"),
+    (480, "1.14.1"),
+    (485, "1.14.2"),
+    (490, "1.14.3"),
+    (498, "1.14.4"),
+    (573, "1.15"),
+    (575, "1.15.1"),
+    (578, "1.15.2"),
+    (735, "1.16"),
+    (736, "1.16.1"),
+    (751, "1.16.2"),
+    (753, "1.16.3"),
+    (754, "1.16.5"),
+    (755, "1.17"),
+    (756, "1.17.1"),
+    (757, "1.18.1"),
+    (758, "1.18.2"),
+    (759, "1.19"),
+    (760, "1.19.2"),
+    (761, "1.19.3"),
+    (762, "1.19.4"),
+    (763, "1.20.1"),
+    (764, "1.20.2"),
+    (765, "1.20.4"),
+    (766, "1.20.6"),
+    (767, "1.21.1"),
+    (768, "1.21.3"),
+    (769, "1.21.4"),
+    (770, "1.21.5"),
+    (771, "1.21.6"),
+    (772, "1.21.8"),
+];
+
+#[derive(Debug, Clone)]
+pub(crate) struct ProtocolTable {
+    names: HashMap<i32, String>,
+}
+
+impl ProtocolTable {
+    pub(crate) fn builtin() -> Self {
+        let names = BUILTIN_PROTOCOL_VERSIONS
+            .iter()
+            .map(|&(protocol, name)| (protocol, name.to_string()))
+            .collect();
+
+        Self { names }
+    }
+
+    /// Merges a user-maintained table over the current one.
+    ///
+    /// The file holds one `<protocol> = <version name>` mapping per line.
+    /// Blank lines and lines starting with `#` are ignored.
+    pub(crate) fn merge_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read protocol table {}", path.display()))?;
+
+        let mut seen = HashMap::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (protocol, name) = line.split_once('=').with_context(|| {
+                format!(
+                    "{}:{line_number}: expected `<protocol> = <version name>`",
+                    path.display()
+                )
+            })?;
+
+            let protocol = protocol
+                .trim()
+                .parse::<i32>()
+                .ok()
+                .filter(|p| *p >= 0)
+                .with_context(|| {
+                    format!(
+                        "{}:{line_number}: `{}` is not a valid protocol number",
+                        path.display(),
+                        protocol.trim()
+                    )
+                })?;
+
+            let name = name.trim();
+
+            if name.is_empty() {
+                anyhow::bail!("{}:{line_number}: missing version name", path.display());
+            }
+
+            if let Some(previous_line) = seen.insert(protocol, line_number) {
+                anyhow::bail!(
+                    "{}:{line_number}: protocol {protocol} is already mapped on line {previous_line}",
+                    path.display()
+                );
+            }
+
+            self.names.insert(protocol, name.to_string());
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn version_name(&self, protocol: i32) -> Option<&str> {
+        self.names.get(&protocol).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Writes `content` to a protocol table file unique to the calling test
+    fn table_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "mmp-protocol-table-{}-{name}.txt",
+            std::process::id()
+        ));
+
+        std::fs::write(&path, content).unwrap();
+
+        path
+    }
+
+    fn merge(name: &str, content: &str) -> anyhow::Result<ProtocolTable> {
+        let path = table_file(name, content);
+        let mut table = ProtocolTable::builtin();
+
+        let result = table.merge_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        result.map(|()| table)
+    }
+
+    #[test]
+    fn file_mapping_overrides_builtin_entry() {
+        assert_eq!(ProtocolTable::builtin().version_name(767), Some("1.21.1"));
+
+        let table = merge(
+            "override",
+            "# Local names\n\n767 = 1.21.1 (modded)\n9999 = 2.0\n",
+        )
+        .unwrap();
+
+        assert_eq!(table.version_name(767), Some("1.21.1 (modded)"));
+        assert_eq!(table.version_name(9999), Some("2.0"));
+        assert_eq!(table.version_name(47), Some("1.8.9"));
+    }
+
+    #[test]
+    fn rejects_malformed_line() {
+        let why = merge("malformed", "767 = 1.21.1\n768 1.21.2\n").unwrap_err();
+
+        assert!(why
+            .to_string()
+            .ends_with(":2: expected `<protocol> = <version name>`"));
+    }
+
+    #[test]
+    fn rejects_negative_protocol() {
+        let why = merge("negative", "-1 = snapshot\n").unwrap_err();
+
+        assert!(why
+            .to_string()
+            .ends_with(":1: `-1` is not a valid protocol number"));
+    }
+
+    #[test]
+    fn rejects_duplicate_protocol() {
+        let why = merge("duplicate", "800 = a\n801 = b\n800 = c\n").unwrap_err();
+
+        assert!(why
+            .to_string()
+            .ends_with(":3: protocol 800 is already mapped on line 1"));
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Starts the server on demand, through a shell command run when players try to join
+pub(crate) struct WakeCommand {
+    command: String,
+    cooldown: Duration,
+    last_started: Mutex<Option<Instant>>,
+}
+
+impl WakeCommand {
+    pub(crate) fn new(command: String, cooldown: Duration) -> Self {
+        Self {
+            command,
+            cooldown,
+            last_started: Mutex::default(),
+        }
+    }
+
+    /// Runs the command in the background, unless it was already started less than the cooldown ago
+    pub(crate) fn trigger(&self) {
+        {
+            let mut last_started = self.last_started.lock().unwrap();
+
+            if last_started.is_some_and(|started| started.elapsed() < self.cooldown) {
+                return;
+            }
+
+            // Set even if spawning fails, so a broken command is not retried on every login
+            *last_started = Some(Instant::now());
+        }
+
+        info!("Running wake command {:?}", self.command);
+
+        let child = shell_command(&self.command).spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(why) => {
+                warn!("Unable to run wake command: {why}");
+                return;
+            }
+        };
+
+        tokio::spawn(async move {
+            match child.wait().await {
+                Ok(status) if status.success() => info!("Wake command finished"),
+                Ok(status) => warn!("Wake command failed with {status}"),
+                Err(why) => warn!("Unable to wait for the wake command: {why}"),
+            }
+        });
+    }
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+
+    shell
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+
+    shell
+}
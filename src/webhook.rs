@@ -0,0 +1,267 @@
+use std::{
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Serialize;
+use tracing::{debug, warn};
+
+/// How long a notification may take before it is given up
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// At most one blocked login is notified per interval, so a scanner cannot flood the channel
+const BLOCKED_LOGIN_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum WebhookEvent {
+    MaintenanceToggled {
+        maintenance: bool,
+    },
+    LoginBlocked {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        ip: IpAddr,
+        /// Blocked logins left out since the previous notification
+        suppressed: u64,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload {
+    /// Human readable summary, the field Slack and compatible services display
+    text: String,
+    /// Seconds since the Unix epoch
+    timestamp: u64,
+    #[serde(flatten)]
+    event: WebhookEvent,
+}
+
+#[derive(Default)]
+struct BlockedLogins {
+    last_notified: Option<Instant>,
+    suppressed: u64,
+}
+
+/// Notifies an HTTP endpoint, such as a Slack incoming webhook, of maintenance events
+pub(crate) struct Webhook {
+    url: reqwest::Url,
+    client: reqwest::Client,
+    blocked_logins: Mutex<BlockedLogins>,
+}
+
+impl Webhook {
+    pub(crate) fn new(url: reqwest::Url) -> anyhow::Result<Self> {
+        if url.scheme() == "https" && !cfg!(feature = "webhook-tls") {
+            anyhow::bail!(
+                "Webhook URL {url} needs HTTPS, which this build lacks: use an http:// URL \
+                 or build with the webhook-tls feature"
+            );
+        }
+
+        // reqwest leaves picking the cryptography of rustls to the application
+        #[cfg(feature = "webhook-tls")]
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            url,
+            client,
+            blocked_logins: Mutex::default(),
+        })
+    }
+
+    pub(crate) fn maintenance_toggled(&self, maintenance: bool) {
+        let text = if maintenance {
+            "Maintenance mode enabled"
+        } else {
+            "Maintenance mode disabled, players are let through"
+        };
+
+        self.send(
+            text.to_string(),
+            WebhookEvent::MaintenanceToggled { maintenance },
+        );
+    }
+
+    /// `username` is unknown for clients older than 1.7
+    pub(crate) fn login_blocked(&self, username: Option<&str>, ip: IpAddr) {
+        self.login_blocked_at(username, ip, Instant::now());
+    }
+
+    fn login_blocked_at(&self, username: Option<&str>, ip: IpAddr, now: Instant) {
+        let suppressed = {
+            let mut blocked_logins = self.blocked_logins.lock().unwrap();
+
+            if blocked_logins
+                .last_notified
+                .is_some_and(|notified| now.duration_since(notified) < BLOCKED_LOGIN_INTERVAL)
+            {
+                blocked_logins.suppressed += 1;
+                return;
+            }
+
+            blocked_logins.last_notified = Some(now);
+            std::mem::take(&mut blocked_logins.suppressed)
+        };
+
+        let mut text = match username {
+            Some(username) => format!("Blocked login of {username} ({ip}) during maintenance"),
+            None => format!("Blocked login from {ip} during maintenance"),
+        };
+
+        if suppressed > 0 {
+            text.push_str(&format!(", {suppressed} more since the last notification"));
+        }
+
+        self.send(
+            text,
+            WebhookEvent::LoginBlocked {
+                username: username.map(str::to_string),
+                ip,
+                suppressed,
+            },
+        );
+    }
+
+    /// Posts the event in the background, so connections never wait for the endpoint
+    fn send(&self, text: String, event: WebhookEvent) {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let request = self.client.post(self.url.clone()).json(&WebhookPayload {
+            text,
+            timestamp,
+            event,
+        });
+
+        tokio::spawn(async move {
+            match request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+            {
+                Ok(_) => debug!("Webhook notified"),
+                Err(why) => warn!("Unable to notify the webhook: {why}"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+
+    /// Answers every request on a local HTTP endpoint, passing on the JSON bodies
+    async fn spawn_endpoint() -> (reqwest::Url, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (bodies_tx, bodies_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let bodies_tx = bodies_tx.clone();
+
+                tokio::spawn(async move {
+                    let mut socket = BufReader::new(socket);
+
+                    loop {
+                        let mut content_length = 0;
+
+                        loop {
+                            let mut line = String::new();
+                            if socket.read_line(&mut line).await.unwrap() == 0 {
+                                return;
+                            }
+
+                            let line = line.trim_end().to_ascii_lowercase();
+                            if line.is_empty() {
+                                break;
+                            }
+
+                            if let Some(length) = line.strip_prefix("content-length:") {
+                                content_length = length.trim().parse().unwrap();
+                            }
+                        }
+
+                        let mut body = vec![0; content_length];
+                        socket.read_exact(&mut body).await.unwrap();
+                        bodies_tx
+                            .send(serde_json::from_slice(&body).unwrap())
+                            .unwrap();
+
+                        socket
+                            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                            .await
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        (url.parse().unwrap(), bodies_rx)
+    }
+
+    async fn next_body(
+        bodies_rx: &mut mpsc::UnboundedReceiver<serde_json::Value>,
+    ) -> serde_json::Value {
+        tokio::time::timeout(Duration::from_secs(5), bodies_rx.recv())
+            .await
+            .expect("no notification sent")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn notifies_one_blocked_login_per_interval() {
+        let (url, mut bodies_rx) = spawn_endpoint().await;
+        let webhook = Webhook::new(url).unwrap();
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        let start = Instant::now();
+
+        webhook.login_blocked_at(Some("Steve"), ip, start);
+        webhook.login_blocked_at(Some("Alex"), ip, start + Duration::from_secs(1));
+        webhook.login_blocked_at(None, ip, start + Duration::from_secs(59));
+        webhook.login_blocked_at(Some("Notch"), ip, start + BLOCKED_LOGIN_INTERVAL);
+
+        // Sent in the background, so not necessarily in order
+        let mut bodies = [
+            next_body(&mut bodies_rx).await,
+            next_body(&mut bodies_rx).await,
+        ];
+        bodies.sort_by_key(|body| body["suppressed"].as_u64());
+
+        assert_eq!(bodies[0]["event"], "login_blocked");
+        assert_eq!(bodies[0]["username"], "Steve");
+        assert_eq!(bodies[0]["suppressed"], 0);
+
+        assert_eq!(bodies[1]["username"], "Notch");
+        assert_eq!(bodies[1]["suppressed"], 2);
+        assert_eq!(
+            bodies[1]["text"],
+            "Blocked login of Notch (127.0.0.1) during maintenance, 2 more since the last notification"
+        );
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(bodies_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn requires_tls_for_https_urls() {
+        let webhook = Webhook::new("https://hooks.example.com/".parse().unwrap());
+
+        assert_eq!(webhook.is_ok(), cfg!(feature = "webhook-tls"));
+    }
+}